@@ -0,0 +1,134 @@
+//! Sprite sheet animation.
+//!
+//! A sheet is a single image with every frame laid out left to right, all the
+//! same size. Each clip is a run of consecutive frames on the sheet. Sheets
+//! are drawn facing right; things facing left get flipped when drawn.
+
+use ggez::graphics::Rect;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClipKind {
+    Idle,
+    Run,
+    Jump,
+    Fall,
+    Swing,
+    Shoot,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Clip {
+    /// Index of the clip's first frame on the sheet
+    pub first: usize,
+    pub frames: usize,
+    /// Seconds each frame is shown for
+    pub frame_time: f32,
+    /// Looping clips start over when they run out. The others hold their
+    /// last frame, and can't be interrupted by Idle or Run until then.
+    pub looping: bool,
+}
+
+impl Clip {
+    fn duration(&self) -> f32 {
+        self.frames as f32 * self.frame_time
+    }
+}
+
+#[derive(Debug)]
+pub struct Sheet {
+    /// Total number of frames on the sheet
+    pub frames: usize,
+    pub idle: Clip,
+    pub run: Clip,
+    pub jump: Clip,
+    pub fall: Clip,
+    pub swing: Clip,
+    pub shoot: Clip,
+}
+
+impl Sheet {
+    pub fn clip(&self, kind: ClipKind) -> &Clip {
+        match kind {
+            ClipKind::Idle => &self.idle,
+            ClipKind::Run => &self.run,
+            ClipKind::Jump => &self.jump,
+            ClipKind::Fall => &self.fall,
+            ClipKind::Swing => &self.swing,
+            ClipKind::Shoot => &self.shoot,
+        }
+    }
+
+    /// The part of the sheet image holding `frame`, in the 0 to 1 texture
+    /// coordinates DrawParam::src wants
+    pub fn src_rect(&self, frame: usize) -> Rect {
+        let width = 1.0 / self.frames as f32;
+        Rect::new(frame as f32 * width, 0.0, width, 1.0)
+    }
+}
+
+/// Layout of /player_sheet.png
+pub static PLAYER_SHEET: Sheet = Sheet {
+    frames: 12,
+    idle: Clip { first: 0, frames: 2, frame_time: 0.5, looping: true },
+    run: Clip { first: 2, frames: 4, frame_time: 0.1, looping: true },
+    jump: Clip { first: 6, frames: 1, frame_time: 0.1, looping: false },
+    fall: Clip { first: 7, frames: 1, frame_time: 0.1, looping: false },
+    swing: Clip { first: 8, frames: 2, frame_time: 0.2, looping: true },
+    shoot: Clip { first: 10, frames: 2, frame_time: 0.05, looping: false },
+};
+
+/// Which clip an entity is playing and how far into it it is
+#[derive(Component, Debug)]
+pub struct Animation {
+    pub sheet: &'static Sheet,
+    clip: ClipKind,
+    time: f32,
+}
+
+impl Animation {
+    pub fn new(sheet: &'static Sheet) -> Animation {
+        Animation {
+            sheet,
+            clip: ClipKind::Idle,
+            time: 0.0,
+        }
+    }
+
+    pub fn clip(&self) -> ClipKind {
+        self.clip
+    }
+
+    /// Switches to `kind`, starting it from the top. Asking for the clip
+    /// that's already playing carries on with it instead.
+    ///
+    /// Idle and Run wait for an unfinished one-shot clip to end first, so
+    /// e.g. a shot isn't cut short just because the player is standing still.
+    pub fn play(&mut self, kind: ClipKind) {
+        if kind == self.clip {
+            return;
+        }
+        let current = self.sheet.clip(self.clip);
+        let interruptible = current.looping || self.time >= current.duration();
+        if !interruptible && (kind == ClipKind::Idle || kind == ClipKind::Run) {
+            return;
+        }
+        self.clip = kind;
+        self.time = 0.0;
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    /// Index on the sheet of the frame to show right now
+    pub fn frame(&self) -> usize {
+        let clip = self.sheet.clip(self.clip);
+        let n = (self.time / clip.frame_time) as usize;
+        let n = if clip.looping {
+            n % clip.frames
+        } else {
+            n.min(clip.frames - 1)
+        };
+        clip.first + n
+    }
+}
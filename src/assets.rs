@@ -0,0 +1,225 @@
+//! Images, fonts and sounds, loaded by name from an asset manifest.
+//!
+//! The manifest is RON, mapping each name to a path under resources/:
+//!
+//! ```text
+//! Manifest(
+//!     images: {
+//!         "player": "/player.png",
+//!     },
+//!     fonts: {
+//!         "ui": (path: "/Roboto-Regular.ttf", size: 18),
+//!     },
+//!     sounds: {
+//!         "shoot": "/sounds/shoot.wav",
+//!     },
+//!     music: {
+//!         "level": "/music/level.wav",
+//!     },
+//! )
+//! ```
+//!
+//! In dev mode the image and font files are watched, and anything that
+//! changes on disk is loaded again while the game is running. Sounds are
+//! only read at startup.
+
+use ggez::audio::SoundData;
+use ggez::graphics::{Font, Image};
+use ggez::{Context, GameError, GameResult};
+use ron;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    images: BTreeMap<String, String>,
+    #[serde(default)]
+    fonts: BTreeMap<String, FontEntry>,
+    #[serde(default)]
+    sounds: BTreeMap<String, String>,
+    #[serde(default)]
+    music: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FontEntry {
+    path: String,
+    size: u32,
+}
+
+pub struct Assets {
+    manifest: Manifest,
+    images: HashMap<String, Image>,
+    fonts: HashMap<String, Font>,
+    sounds: HashMap<String, SoundData>,
+    music: HashMap<String, SoundData>,
+    watcher: Option<Watcher>,
+    /// Goes up every time something is reloaded, so whatever holds on to
+    /// copies of the assets knows to drop them
+    generation: u32,
+}
+
+impl Assets {
+    /// Loads everything listed in the manifest at `manifest_path`
+    pub fn load(ctx: &mut Context, manifest_path: &str) -> GameResult<Assets> {
+        let mut source = String::new();
+        ctx.filesystem.open(manifest_path)?.read_to_string(&mut source)?;
+        let manifest: Manifest = ron::de::from_str(&source).map_err(|e| {
+            GameError::ResourceLoadError(format!(
+                "{}: line {}, column {}: {}",
+                manifest_path, e.position.line, e.position.col, e.code
+            ))
+        })?;
+
+        let mut images = HashMap::new();
+        for (name, path) in &manifest.images {
+            images.insert(name.clone(), load_image(ctx, path)?);
+        }
+        let mut fonts = HashMap::new();
+        for (name, entry) in &manifest.fonts {
+            fonts.insert(name.clone(), load_font(ctx, entry)?);
+        }
+        let mut sounds = HashMap::new();
+        for (name, path) in &manifest.sounds {
+            sounds.insert(name.clone(), load_sound(ctx, path)?);
+        }
+        let mut music = HashMap::new();
+        for (name, path) in &manifest.music {
+            music.insert(name.clone(), load_sound(ctx, path)?);
+        }
+
+        Ok(Assets {
+            manifest,
+            images,
+            fonts,
+            sounds,
+            music,
+            watcher: None,
+            generation: 0,
+        })
+    }
+
+    pub fn image(&self, name: &str) -> GameResult<&Image> {
+        self.images.get(name).ok_or_else(|| {
+            GameError::ResourceLoadError(format!("no image named {:?} in the asset manifest", name))
+        })
+    }
+
+    pub fn font(&self, name: &str) -> GameResult<&Font> {
+        self.fonts.get(name).ok_or_else(|| {
+            GameError::ResourceLoadError(format!("no font named {:?} in the asset manifest", name))
+        })
+    }
+
+    pub fn sound(&self, name: &str) -> GameResult<&SoundData> {
+        self.sounds.get(name).ok_or_else(|| {
+            GameError::ResourceLoadError(format!("no sound named {:?} in the asset manifest", name))
+        })
+    }
+
+    /// Every music track, by name
+    pub fn music(&self) -> &HashMap<String, SoundData> {
+        &self.music
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Starts watching the asset files under `resources`, the directory the
+    /// manifest's paths are relative to
+    pub fn watch(&mut self, resources: &Path) {
+        let mut files: Vec<String> = self.manifest.images.values().cloned().collect();
+        files.extend(self.manifest.fonts.values().map(|entry| entry.path.clone()));
+        self.watcher = Some(Watcher::new(resources, files));
+    }
+
+    /// If watching, reloads every asset whose file changed since the last
+    /// call. An asset that fails to load keeps its old version, so a
+    /// half-saved file doesn't take the game down.
+    pub fn reload_changed(&mut self, ctx: &mut Context) {
+        let changed = match self.watcher {
+            Some(ref mut watcher) => watcher.changed(),
+            None => return,
+        };
+        for path in changed {
+            for (name, _) in self.manifest.images.iter().filter(|&(_, p)| *p == path) {
+                match load_image(ctx, &path) {
+                    Ok(image) => {
+                        println!("Reloaded image {}", name);
+                        self.images.insert(name.clone(), image);
+                    }
+                    Err(e) => println!("Could not reload image {}: {}", name, e),
+                }
+            }
+            for (name, entry) in self.manifest.fonts.iter().filter(|&(_, e)| e.path == path) {
+                match load_font(ctx, entry) {
+                    Ok(font) => {
+                        println!("Reloaded font {}", name);
+                        self.fonts.insert(name.clone(), font);
+                    }
+                    Err(e) => println!("Could not reload font {}: {}", name, e),
+                }
+            }
+            self.generation += 1;
+        }
+    }
+}
+
+fn load_image(ctx: &mut Context, path: &str) -> GameResult<Image> {
+    Image::new(ctx, path).map_err(|e| GameError::ResourceLoadError(format!("{}: {}", path, e)))
+}
+
+fn load_sound(ctx: &mut Context, path: &str) -> GameResult<SoundData> {
+    SoundData::new(ctx, path).map_err(|e| GameError::ResourceLoadError(format!("{}: {}", path, e)))
+}
+
+fn load_font(ctx: &mut Context, entry: &FontEntry) -> GameResult<Font> {
+    Font::new(ctx, &entry.path, entry.size)
+        .map_err(|e| GameError::ResourceLoadError(format!("{}: {}", entry.path, e)))
+}
+
+/// Notices asset files changing by polling their modification times. The
+/// handful of files we have makes that cheap enough to do every frame.
+struct Watcher {
+    root: PathBuf,
+    /// Manifest path and the modification time it was last loaded at
+    files: Vec<(String, Option<SystemTime>)>,
+}
+
+impl Watcher {
+    fn new(root: &Path, mut paths: Vec<String>) -> Watcher {
+        paths.sort();
+        paths.dedup();
+        let root = root.to_owned();
+        let files = paths.into_iter()
+            .map(|path| {
+                let modified = modified(&root, &path);
+                (path, modified)
+            })
+            .collect();
+        Watcher { root, files }
+    }
+
+    /// Manifest paths of the files modified since the last call
+    fn changed(&mut self) -> Vec<String> {
+        let mut changed = vec![];
+        for &mut (ref path, ref mut last) in &mut self.files {
+            let now = modified(&self.root, path);
+            if now.is_some() && now != *last {
+                *last = now;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn modified(root: &Path, path: &str) -> Option<SystemTime> {
+    let file = root.join(path.trim_start_matches('/'));
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,341 @@
+//! Sound effects and music.
+//!
+//! The simulation never plays anything itself. Systems push Sounds onto the
+//! Sounds resource, and once a tick the game hands them to Audio, which plays
+//! them through a Backend. The window uses ggez's audio; headless runs, and
+//! machines with no sound device, get NullBackend, which plays nothing.
+
+use ggez::audio::Source;
+use ggez::{Context, GameResult};
+use std::collections::HashMap;
+
+use assets::Assets;
+
+/// The sound effects, each named the same as in the asset manifest
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Sound {
+    Shoot,
+    Jump,
+    HookAttach,
+    HookRelease,
+    Hit,
+    BossPhase,
+}
+
+pub const ALL_SOUNDS: [Sound; 6] = [
+    Sound::Shoot,
+    Sound::Jump,
+    Sound::HookAttach,
+    Sound::HookRelease,
+    Sound::Hit,
+    Sound::BossPhase,
+];
+
+impl Sound {
+    pub fn name(self) -> &'static str {
+        match self {
+            Sound::Shoot => "shoot",
+            Sound::Jump => "jump",
+            Sound::HookAttach => "hook_attach",
+            Sound::HookRelease => "hook_release",
+            Sound::Hit => "hit",
+            Sound::BossPhase => "boss_phase",
+        }
+    }
+}
+
+/// Sounds the simulation asked for this tick
+#[derive(Debug, Default)]
+pub struct Sounds(pub Vec<Sound>);
+
+impl Sounds {
+    pub fn play(&mut self, sound: Sound) {
+        self.0.push(sound);
+    }
+}
+
+/// Volume settings, each from 0 to 1. Effects and music are both scaled by
+/// `master`.
+#[derive(Clone, Copy, Debug)]
+pub struct Volume {
+    pub master: f32,
+    pub effects: f32,
+    pub music: f32,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume {
+            master: 1.0,
+            effects: 0.8,
+            music: 0.5,
+        }
+    }
+}
+
+/// Whatever actually makes the noise
+pub trait Backend {
+    /// Plays a sound effect once, over whatever else is playing
+    fn play(&mut self, sound: Sound, volume: f32);
+    /// Starts a music track looping from the beginning. Returns false if
+    /// there's no such track.
+    fn start_track(&mut self, track: &str, volume: f32) -> bool;
+    fn set_track_volume(&mut self, track: &str, volume: f32);
+    fn stop_track(&mut self, track: &str);
+}
+
+/// Plays nothing
+pub struct NullBackend;
+
+impl Backend for NullBackend {
+    fn play(&mut self, _sound: Sound, _volume: f32) {}
+
+    fn start_track(&mut self, _track: &str, _volume: f32) -> bool {
+        true
+    }
+
+    fn set_track_volume(&mut self, _track: &str, _volume: f32) {}
+
+    fn stop_track(&mut self, _track: &str) {}
+}
+
+/// How many copies of one sound effect can play at once
+const VOICES: usize = 4;
+
+/// Plays through ggez's audio. A ggez Source plays one sound at a time, so
+/// each effect gets a few of them to let shots overlap.
+pub struct GgezBackend {
+    effects: HashMap<Sound, Vec<Source>>,
+    tracks: HashMap<String, Source>,
+}
+
+impl GgezBackend {
+    pub fn new(ctx: &mut Context, assets: &Assets) -> GameResult<GgezBackend> {
+        let mut effects = HashMap::new();
+        for &sound in &ALL_SOUNDS {
+            let data = assets.sound(sound.name())?;
+            let mut voices = vec![];
+            for _ in 0..VOICES {
+                voices.push(Source::from_data(ctx, data.clone())?);
+            }
+            effects.insert(sound, voices);
+        }
+
+        let mut tracks = HashMap::new();
+        for (name, data) in assets.music() {
+            let mut source = Source::from_data(ctx, data.clone())?;
+            source.set_repeat(true);
+            tracks.insert(name.clone(), source);
+        }
+
+        Ok(GgezBackend { effects, tracks })
+    }
+}
+
+impl Backend for GgezBackend {
+    fn play(&mut self, sound: Sound, volume: f32) {
+        let voices = match self.effects.get_mut(&sound) {
+            Some(voices) => voices,
+            None => return,
+        };
+        // If every voice is busy the sound gets dropped, which nobody
+        // notices in the middle of that much noise anyway
+        if let Some(voice) = voices.iter_mut().find(|voice| !voice.playing()) {
+            voice.set_volume(volume);
+            if let Err(e) = voice.play() {
+                println!("Could not play {}: {}", sound.name(), e);
+            }
+        }
+    }
+
+    fn start_track(&mut self, track: &str, volume: f32) -> bool {
+        match self.tracks.get_mut(track) {
+            Some(source) => {
+                source.stop();
+                source.set_volume(volume);
+                if let Err(e) = source.play() {
+                    println!("Could not play {}: {}", track, e);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_track_volume(&mut self, track: &str, volume: f32) {
+        if let Some(source) = self.tracks.get_mut(track) {
+            source.set_volume(volume);
+        }
+    }
+
+    fn stop_track(&mut self, track: &str) {
+        if let Some(source) = self.tracks.get_mut(track) {
+            source.stop();
+        }
+    }
+}
+
+/// A music track and how loud it is, from 0 to 1, partway through a fade
+#[derive(Debug)]
+struct Track {
+    name: String,
+    gain: f32,
+}
+
+pub struct Audio {
+    backend: Box<dyn Backend>,
+    volume: Volume,
+    /// The track playing, or fading in
+    current: Option<Track>,
+    /// Tracks on their way out
+    fading: Vec<Track>,
+    /// Seconds a fade in or out takes
+    fade_time: f32,
+    /// The last track asked for that doesn't exist, so asking again every
+    /// tick only complains once
+    missing: Option<String>,
+}
+
+impl Audio {
+    pub fn new(backend: Box<dyn Backend>, volume: Volume) -> Audio {
+        Audio {
+            backend,
+            volume,
+            current: None,
+            fading: vec![],
+            fade_time: 0.0,
+            missing: None,
+        }
+    }
+
+    /// An Audio that plays nothing
+    pub fn null() -> Audio {
+        Audio::new(Box::new(NullBackend), Volume::default())
+    }
+
+    pub fn volume(&self) -> Volume {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: Volume) {
+        self.volume = volume;
+        self.apply_track_volumes();
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        let volume = self.volume.master * self.volume.effects;
+        if volume > 0.0 {
+            self.backend.play(sound, volume);
+        }
+    }
+
+    /// Switches to `track`, fading the old one out and the new one in over
+    /// `fade_time` seconds. Does nothing if `track` is already playing, or
+    /// doesn't exist.
+    pub fn play_music(&mut self, track: &str, fade_time: f32) {
+        if self.current.as_ref().map_or(false, |t| t.name == track) {
+            return;
+        }
+        if self.missing.as_ref().map_or(false, |name| name == track) {
+            return;
+        }
+
+        // A track on its way out picks up again from where it is
+        let gain = match self.fading.iter().position(|t| t.name == track) {
+            Some(i) => self.fading.remove(i).gain,
+            None => {
+                if !self.backend.start_track(track, 0.0) {
+                    println!("There's no music track named {:?}", track);
+                    self.missing = Some(track.to_owned());
+                    return;
+                }
+                0.0
+            }
+        };
+        self.missing = None;
+        self.fade_time = fade_time;
+        if let Some(old) = self.current.take() {
+            self.fading.push(old);
+        }
+        self.current = Some(Track {
+            name: track.to_owned(),
+            gain: if fade_time > 0.0 { gain } else { 1.0 },
+        });
+        self.apply_track_volumes();
+    }
+
+    /// Fades out whatever's playing over `fade_time` seconds
+    pub fn stop_music(&mut self, fade_time: f32) {
+        self.fade_time = fade_time;
+        if let Some(old) = self.current.take() {
+            self.fading.push(old);
+        }
+    }
+
+    /// Moves fades along by `dt` seconds
+    pub fn update(&mut self, dt: f32) {
+        let step = if self.fade_time > 0.0 { dt / self.fade_time } else { 1.0 };
+        if let Some(ref mut current) = self.current {
+            current.gain = (current.gain + step).min(1.0);
+        }
+        for track in &mut self.fading {
+            track.gain = (track.gain - step).max(0.0);
+        }
+
+        let backend = &mut self.backend;
+        self.fading.retain(|track| {
+            if track.gain == 0.0 {
+                backend.stop_track(&track.name);
+                false
+            } else {
+                true
+            }
+        });
+        self.apply_track_volumes();
+    }
+
+    fn apply_track_volumes(&mut self) {
+        let music = self.volume.master * self.volume.music;
+        for track in self.current.iter().chain(&self.fading) {
+            self.backend.set_track_volume(&track.name, track.gain * music);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Knows one track, and counts how often anything asks to start one
+    struct OneTrack(Rc<RefCell<u32>>);
+
+    impl Backend for OneTrack {
+        fn play(&mut self, _sound: Sound, _volume: f32) {}
+
+        fn start_track(&mut self, track: &str, _volume: f32) -> bool {
+            *self.0.borrow_mut() += 1;
+            track == "title"
+        }
+
+        fn set_track_volume(&mut self, _track: &str, _volume: f32) {}
+
+        fn stop_track(&mut self, _track: &str) {}
+    }
+
+    #[test]
+    fn missing_tracks_leave_the_music_alone() {
+        let starts = Rc::new(RefCell::new(0));
+        let mut audio = Audio::new(Box::new(OneTrack(starts.clone())), Volume::default());
+        audio.play_music("title", 0.0);
+        for _ in 0..10 {
+            audio.play_music("boss", 1.0);
+            audio.update(0.1);
+        }
+        assert_eq!(audio.current.as_ref().map(|t| t.name.as_str()), Some("title"));
+        assert!(audio.fading.is_empty());
+        // Once for the title, once to find out there's no boss music
+        assert_eq!(*starts.borrow(), 2);
+    }
+}
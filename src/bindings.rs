@@ -0,0 +1,239 @@
+//! Control bindings: which keys, gamepad buttons and sticks drive which
+//! Inputs.
+//!
+//! Bindings are stored as RON, naming keys the way SDL does ("Left", "Space",
+//! "Z") and gamepad buttons and axes the way SDL game controllers do ("a",
+//! "dpleft", "leftx"):
+//!
+//! ```text
+//! Bindings(
+//!     keys: [("Left", LEFT), ("Space", JUMP)],
+//!     buttons: [("a", JUMP)],
+//!     stick_x: Some("leftx"),
+//!     stick_y: Some("lefty"),
+//!     dead_zone: 0.25,
+//! )
+//! ```
+
+use ggez::event::{Axis, Button, Keycode};
+use ron;
+use std::collections::HashMap;
+
+use {Input, ALL_INPUTS};
+
+/// The on-disk form of Bindings
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename = "Bindings")]
+struct BindingsFile {
+    keys: Vec<(String, Input)>,
+    #[serde(default)]
+    buttons: Vec<(String, Input)>,
+    #[serde(default)]
+    stick_x: Option<String>,
+    #[serde(default)]
+    stick_y: Option<String>,
+    #[serde(default = "default_dead_zone")]
+    dead_zone: f32,
+}
+
+fn default_dead_zone() -> f32 {
+    0.25
+}
+
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    keys: HashMap<Keycode, Input>,
+    buttons: HashMap<Button, Input>,
+    stick_x: Option<Axis>,
+    stick_y: Option<Axis>,
+    /// Stick deflections smaller than this, from 0 to 1, count as centered
+    dead_zone: f32,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            (Keycode::Left, Input::LEFT),
+            (Keycode::Right, Input::RIGHT),
+            (Keycode::Up, Input::UP),
+            (Keycode::Down, Input::DOWN),
+            (Keycode::Space, Input::JUMP),
+            (Keycode::Z, Input::SHOOT),
+            (Keycode::X, Input::TOOL),
+            (Keycode::C, Input::SWITCH),
+        ];
+        let buttons = [
+            (Button::DPadLeft, Input::LEFT),
+            (Button::DPadRight, Input::RIGHT),
+            (Button::DPadUp, Input::UP),
+            (Button::DPadDown, Input::DOWN),
+            (Button::A, Input::JUMP),
+            (Button::X, Input::SHOOT),
+            (Button::B, Input::TOOL),
+            (Button::Y, Input::SWITCH),
+        ];
+        Bindings {
+            keys: keys.iter().cloned().collect(),
+            buttons: buttons.iter().cloned().collect(),
+            stick_x: Some(Axis::LeftX),
+            stick_y: Some(Axis::LeftY),
+            dead_zone: default_dead_zone(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn parse(source: &str) -> Result<Bindings, String> {
+        let file: BindingsFile = ron::de::from_str(source).map_err(|e| {
+            format!("line {}, column {}: {}", e.position.line, e.position.col, e.code)
+        })?;
+
+        let mut keys = HashMap::new();
+        for (name, input) in file.keys {
+            let key = Keycode::from_name(&name).ok_or_else(|| format!("unknown key {:?}", name))?;
+            keys.insert(key, input);
+        }
+        let mut buttons = HashMap::new();
+        for (name, input) in file.buttons {
+            let button = Button::from_string(&name).ok_or_else(|| format!("unknown gamepad button {:?}", name))?;
+            buttons.insert(button, input);
+        }
+        let parse_axis = |name: Option<String>| match name {
+            Some(name) => Axis::from_string(&name)
+                .map(Some)
+                .ok_or_else(|| format!("unknown gamepad axis {:?}", name)),
+            None => Ok(None),
+        };
+
+        Ok(Bindings {
+            keys,
+            buttons,
+            stick_x: parse_axis(file.stick_x)?,
+            stick_y: parse_axis(file.stick_y)?,
+            dead_zone: file.dead_zone,
+        })
+    }
+
+    pub fn to_ron(&self) -> String {
+        // Sorted so saving the same bindings twice gives the same file
+        let mut keys: Vec<(String, Input)> = self.keys.iter()
+            .map(|(key, &input)| (key.name(), input))
+            .collect();
+        keys.sort_by_key(|&(ref name, input)| (input, name.clone()));
+        let mut buttons: Vec<(String, Input)> = self.buttons.iter()
+            .map(|(button, &input)| (button.string(), input))
+            .collect();
+        buttons.sort_by_key(|&(ref name, input)| (input, name.clone()));
+
+        let file = BindingsFile {
+            keys,
+            buttons,
+            stick_x: self.stick_x.map(|axis| axis.string()),
+            stick_y: self.stick_y.map(|axis| axis.string()),
+            dead_zone: self.dead_zone,
+        };
+        ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::new())
+            .expect("bindings should always serialize")
+    }
+
+    pub fn key(&self, key: Keycode) -> Option<Input> {
+        self.keys.get(&key).cloned()
+    }
+
+    pub fn button(&self, button: Button) -> Option<Input> {
+        self.buttons.get(&button).cloned()
+    }
+
+    /// Turns a raw stick reading into (x, y) deflections if `axis` is one of
+    /// the bound stick axes. Up is positive y, like in the world.
+    pub fn stick(&self, axis: Axis, value: i16) -> Option<(Option<f32>, Option<f32>)> {
+        let mut deflection = f32::from(value) / 32767.0;
+        if deflection.abs() < self.dead_zone {
+            deflection = 0.0;
+        }
+        let deflection = deflection.max(-1.0).min(1.0);
+
+        if Some(axis) == self.stick_x {
+            Some((Some(deflection), None))
+        } else if Some(axis) == self.stick_y {
+            // SDL sticks point down for positive y
+            Some((None, Some(-deflection)))
+        } else {
+            None
+        }
+    }
+
+    /// Human-readable names of everything bound to `input`
+    pub fn describe(&self, input: Input) -> String {
+        let mut names: Vec<String> = self.keys.iter()
+            .filter(|&(_, &i)| i == input)
+            .map(|(key, _)| key.name())
+            .collect();
+        names.sort();
+        let mut buttons: Vec<String> = self.buttons.iter()
+            .filter(|&(_, &i)| i == input)
+            .map(|(button, _)| format!("pad {}", button.string()))
+            .collect();
+        buttons.sort();
+        names.extend(buttons);
+        if names.is_empty() {
+            "(unbound)".to_owned()
+        } else {
+            names.join(", ")
+        }
+    }
+
+    fn bind_key(&mut self, key: Keycode, input: Input) {
+        self.keys.retain(|_, &mut i| i != input);
+        self.keys.insert(key, input);
+    }
+
+    fn bind_button(&mut self, button: Button, input: Input) {
+        self.buttons.retain(|_, &mut i| i != input);
+        self.buttons.insert(button, input);
+    }
+}
+
+/// The rebinding screen's progress. It walks through every Input in turn and
+/// binds it to the next key or button pressed.
+pub struct Rebinder {
+    bindings: Bindings,
+    step: usize,
+}
+
+impl Rebinder {
+    pub fn new(current: &Bindings) -> Rebinder {
+        Rebinder {
+            bindings: current.clone(),
+            step: 0,
+        }
+    }
+
+    /// The Input waiting for a key, or None once they've all been bound
+    pub fn current(&self) -> Option<Input> {
+        ALL_INPUTS.get(self.step).cloned()
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn bind_key(&mut self, key: Keycode) {
+        if let Some(input) = self.current() {
+            self.bindings.bind_key(key, input);
+            self.step += 1;
+        }
+    }
+
+    pub fn bind_button(&mut self, button: Button) {
+        if let Some(input) = self.current() {
+            self.bindings.bind_button(button, input);
+            self.step += 1;
+        }
+    }
+
+    /// Leaves the current Input bound the way it was
+    pub fn skip(&mut self) {
+        self.step += 1;
+    }
+}
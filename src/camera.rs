@@ -0,0 +1,143 @@
+//! The view onto the world.
+//!
+//! The camera follows the player with a little slack, stays inside the
+//! level's bounds, and shakes when things get hit. It also converts between
+//! world coordinates, with y up, and screen pixels.
+
+use ggez::graphics::{Point2, Vector2};
+
+/// How far the view can be thrown by a full-trauma shake, in world units
+const MAX_SHAKE: f32 = 8.0;
+
+/// Trauma lost per second
+const TRAUMA_DECAY: f32 = 1.5;
+
+/// What part of the world is on screen.
+///
+/// The camera trails its target: the target can wander around a dead zone in
+/// the middle of the screen without the view moving, and once it leaves, the
+/// view eases toward it. Screen shake is driven by `trauma`, which hits and
+/// slams add to and which wears off over time.
+#[derive(Debug)]
+pub struct Camera {
+    /// The world position at the center of the screen, before shake
+    pub pos: Point2,
+    /// Screen pixels per world unit
+    pub zoom: f32,
+    /// Half-size of the box around the center, in world units, that the
+    /// target can move in without the camera following
+    pub dead_zone: Vector2,
+    /// How quickly the camera catches up. Larger is snappier.
+    pub smoothing: f32,
+    /// The view is kept inside this (min, max) world rectangle if there is one
+    pub bounds: Option<(Point2, Point2)>,
+    /// From 0 to 1. Shake grows with the square of this.
+    pub trauma: f32,
+    /// Screen size in pixels
+    viewport: Vector2,
+    /// Drives the shake noise
+    time: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new(640, 360)
+    }
+}
+
+impl Camera {
+    pub fn new(screen_width: u32, screen_height: u32) -> Camera {
+        Camera {
+            pos: Point2::origin(),
+            zoom: 1.0,
+            dead_zone: Vector2::new(32.0, 24.0),
+            smoothing: 8.0,
+            bounds: None,
+            trauma: 0.0,
+            viewport: Vector2::new(screen_width as f32, screen_height as f32),
+            time: 0.0,
+        }
+    }
+
+    pub fn set_viewport(&mut self, screen_width: u32, screen_height: u32) {
+        self.viewport = Vector2::new(screen_width as f32, screen_height as f32);
+        self.clamp();
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    /// Jumps straight to `target` with no easing, e.g. when a level starts
+    pub fn snap_to(&mut self, target: Point2) {
+        self.pos = target;
+        self.clamp();
+    }
+
+    /// Eases toward `target` over a tick of `dt` seconds, and wears off some
+    /// trauma.
+    pub fn update(&mut self, target: Option<Point2>, dt: f32) {
+        if let Some(target) = target {
+            let offset = target - self.pos;
+            let excess = Vector2::new(
+                offset.x - offset.x.max(-self.dead_zone.x).min(self.dead_zone.x),
+                offset.y - offset.y.max(-self.dead_zone.y).min(self.dead_zone.y),
+            );
+            // Frame-rate independent exponential easing
+            let t = 1.0 - (-self.smoothing * dt).exp();
+            self.pos += excess * t;
+            self.clamp();
+        }
+
+        self.time += dt;
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+    }
+
+    fn clamp(&mut self) {
+        if let Some((min, max)) = self.bounds {
+            let half = self.viewport / (2.0 * self.zoom);
+            self.pos.x = clamp_axis(self.pos.x, min.x + half.x, max.x - half.x);
+            self.pos.y = clamp_axis(self.pos.y, min.y + half.y, max.y - half.y);
+        }
+    }
+
+    /// How far the shake is currently throwing the view
+    fn shake_offset(&self) -> Vector2 {
+        let shake = self.trauma * self.trauma;
+        // Cheap deterministic noise: a couple of out-of-step sine waves per
+        // axis. Random numbers would jitter differently every frame drawn.
+        let noise = |seed: f32| {
+            let t = self.time * 30.0 + seed;
+            ((t).sin() + (t * 2.3 + seed).sin() * 0.5) / 1.5
+        };
+        Vector2::new(noise(0.0), noise(17.0)) * MAX_SHAKE * shake
+    }
+
+    /// Translates the world coordinate system, which has Y pointing up, to
+    /// the screen coordinate system, which has Y pointing downward and the
+    /// origin at the top-left.
+    pub fn world_to_screen(&self, point: Point2) -> Point2 {
+        let center = self.pos + self.shake_offset();
+        let x = (point.x - center.x) * self.zoom + self.viewport.x / 2.0;
+        let y = self.viewport.y / 2.0 - (point.y - center.y) * self.zoom;
+        Point2::new(x, y)
+    }
+
+    /// The inverse of `world_to_screen`, e.g. for finding what was clicked
+    pub fn screen_to_world(&self, point: Point2) -> Point2 {
+        let center = self.pos + self.shake_offset();
+        let x = (point.x - self.viewport.x / 2.0) / self.zoom + center.x;
+        let y = (self.viewport.y / 2.0 - point.y) / self.zoom + center.y;
+        Point2::new(x, y)
+    }
+}
+
+/// Clamps `x` to [`min`, `max`], or centers it if the range is inverted
+/// (the bounds are smaller than the view)
+fn clamp_axis(x: f32, min: f32, max: f32) -> f32 {
+    if min > max {
+        (min + max) / 2.0
+    } else {
+        x.max(min).min(max)
+    }
+}
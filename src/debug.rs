@@ -0,0 +1,347 @@
+//! The debug overlay, toggled with F3 while playing.
+//!
+//! It shows the frame rate with a graph of recent frame times, how full the
+//! pools are, how many entities have each component, the player's movement
+//! state, collider outlines and how far each hook reaches. Clicking an
+//! entity lists every component on it.
+//!
+//! The physics settings can be tuned live: Page Up and Page Down pick one,
+//! - and = change it by 5%, and F6 prints them all, ready to paste into
+//! physics.ron.
+//!
+//! Text is only rebuilt a few times a second, and not at all while the
+//! overlay is hidden.
+
+use super::*;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+/// Frames of history in the frame time graph
+const GRAPH_FRAMES: usize = 120;
+
+/// Seconds between refreshes of the overlay's text
+const REFRESH_INTERVAL: f32 = 0.25;
+
+/// How close, in world units, a click has to be to an entity to pick it
+const PICK_RADIUS: f32 = 16.0;
+
+/// How much one press changes a physics setting by, as a fraction of it
+const TWEAK_STEP: f32 = 0.05;
+
+/// A component the overlay knows how to count and print
+struct ComponentInfo {
+    name: &'static str,
+    count: fn(&World) -> usize,
+    describe: fn(&World, Entity) -> Option<String>,
+}
+
+fn count<T: Component>(world: &World) -> usize {
+    world.read_storage::<T>().join().count()
+}
+
+fn describe<T: Component + Debug>(world: &World, ent: Entity) -> Option<String> {
+    world.read_storage::<T>().get(ent).map(|c| format!("{:?}", c))
+}
+
+macro_rules! components {
+    ($($component:ty),*) => {
+        vec![$(ComponentInfo {
+            name: stringify!($component),
+            count: count::<$component>,
+            describe: describe::<$component>,
+        }),*]
+    };
+}
+
+/// Every component registered with the world
+fn all_components() -> Vec<ComponentInfo> {
+    components!(
+        Pos, Vel, IsPlayer, BulletStatus, Facing, Aim, HasGravity, ShootCooldown,
+        IsJumping, JumpWindows, Grounded, TileCollider, IsHook, IsSwingTarget, SwingData_,
+        IsBoss, Health, Damage, HitStun, Invulnerable, Dead, BossPhase, PhaseTimer,
+        IsEnemyBullet, Collider, Patrol, Slamming, Animation, Sprite, Weapons,
+        WeaponPickup, Particle, Emitter
+    )
+}
+
+pub struct DebugOverlay {
+    visible: bool,
+    components: Vec<ComponentInfo>,
+    /// Seconds each recent frame took, oldest first
+    frame_times: VecDeque<f32>,
+    /// The entity whose components are listed, if one's been clicked
+    selected: Option<Entity>,
+    /// Which physics setting the tweak keys change
+    physics_param: usize,
+    /// Text for the left-hand stats and the inspector panel
+    stats: Vec<graphics::Text>,
+    inspector: Vec<graphics::Text>,
+    since_refresh: f32,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        DebugOverlay {
+            visible: false,
+            components: all_components(),
+            frame_times: VecDeque::with_capacity(GRAPH_FRAMES),
+            selected: None,
+            physics_param: 0,
+            stats: vec![],
+            inspector: vec![],
+            since_refresh: REFRESH_INTERVAL,
+        }
+    }
+}
+
+impl DebugOverlay {
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.since_refresh = REFRESH_INTERVAL;
+    }
+
+    /// Notes how long the last frame took, for the graph
+    pub fn record_frame(&mut self, seconds: f32) {
+        if self.frame_times.len() == GRAPH_FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(seconds);
+    }
+
+    /// Selects whatever entity is nearest the screen point `(x, y)`, or
+    /// clears the selection if nothing's close enough
+    pub fn pick(&mut self, world: &World, x: i32, y: i32) {
+        let camera = world.read_resource::<Camera>();
+        let point = camera.screen_to_world(Point2::new(x as f32, y as f32));
+
+        let entities = world.entities();
+        let pos = world.read_storage::<Pos>();
+        let sprite = world.read_storage::<Sprite>();
+        let bullet = world.read_storage::<BulletStatus>();
+        self.selected = (&*entities, &pos, sprite.maybe(), bullet.maybe()).join()
+            // Skip pooled entities that aren't in use
+            .filter(|&(_, _, sprite, bullet)| {
+                sprite.map_or(true, |s| s.visible) && !match bullet {
+                    Some(BulletStatus::Dead) => true,
+                    _ => false,
+                }
+            })
+            .map(|(ent, pos, _, _)| (ent, pos.0.distance(&point)))
+            .filter(|&(_, d)| d < PICK_RADIUS)
+            .min_by(|x, y| PartialOrd::partial_cmp(&x.1, &y.1).unwrap())
+            .map(|(ent, _)| ent);
+        self.since_refresh = REFRESH_INTERVAL;
+    }
+
+    /// Handles the physics tweaking keys. Returns whether the key was one
+    /// of them.
+    pub fn key_down(&mut self, world: &World, keycode: Keycode) -> bool {
+        let mut physics = world.write_resource::<PhysicsConfig>();
+        let count = physics.params().len();
+        match keycode {
+            Keycode::PageUp => self.physics_param = (self.physics_param + count - 1) % count,
+            Keycode::PageDown => self.physics_param = (self.physics_param + 1) % count,
+            Keycode::Minus | Keycode::Equals => {
+                let step = if keycode == Keycode::Minus { 1.0 - TWEAK_STEP } else { 1.0 + TWEAK_STEP };
+                let mut tweaked = physics.clone();
+                if let Some(value) = tweaked.param_mut(self.physics_param) {
+                    *value *= step;
+                }
+                // Leave it alone rather than let it stop making sense
+                match tweaked.validate() {
+                    Ok(()) => *physics = tweaked,
+                    Err(e) => println!("Can't change that: {}", e),
+                }
+            }
+            Keycode::F6 => println!("{}", physics.to_ron()),
+            _ => return false,
+        }
+        self.since_refresh = REFRESH_INTERVAL;
+        true
+    }
+
+    /// Rebuilds the overlay's text if it's due, after a tick of `dt` seconds
+    pub fn update(&mut self, ctx: &mut Context, world: &World, font: &graphics::Font, dt: f32) -> GameResult<()> {
+        if !self.visible {
+            return Ok(());
+        }
+        self.since_refresh += dt;
+        if self.since_refresh < REFRESH_INTERVAL {
+            return Ok(());
+        }
+        self.since_refresh = 0.0;
+
+        let mut stats = vec![];
+        let worst = self.frame_times.iter().cloned().fold(0.0, f32::max);
+        stats.push(format!("FPS {:.0}  worst frame {:.1} ms", timer::get_fps(ctx), worst * 1000.0));
+        stats.push(String::new());
+
+        let players: Vec<Entity> = {
+            let entities = world.entities();
+            let is_player = world.read_storage::<IsPlayer>();
+            (&*entities, &is_player).join().map(|(ent, _)| ent).collect()
+        };
+        for player in players {
+            stats.push("Player".to_owned());
+            for name in &["Pos", "Vel", "IsJumping", "JumpWindows", "SwingData_"] {
+                let info = self.components.iter().find(|info| info.name == *name).unwrap();
+                let value = (info.describe)(world, player).unwrap_or_else(|| "-".to_owned());
+                stats.push(format!("  {}", value));
+            }
+            // Animation's Debug output is mostly the sheet, so just name the clip
+            if let Some(animation) = world.read_storage::<Animation>().get(player) {
+                stats.push(format!("  Clip {:?}", animation.clip()));
+            }
+            stats.push(String::new());
+        }
+
+        stats.push("Physics".to_owned());
+        for (i, (name, value)) in world.read_resource::<PhysicsConfig>().params().into_iter().enumerate() {
+            let cursor = if i == self.physics_param { ">" } else { " " };
+            stats.push(format!("{} {} {:.3}", cursor, name, value));
+        }
+        stats.push(String::new());
+
+        stats.push("Pools".to_owned());
+        {
+            let pools = world.read_resource::<Pools>();
+            let all = [
+                ("player bullets", &pools.player_bullets),
+                ("enemy bullets", &pools.enemy_bullets),
+                ("particles", &pools.particles),
+            ];
+            for &(name, pool) in &all {
+                let pool_stats = pool.stats();
+                stats.push(format!(
+                    "  {} {}/{} (peak {}, ran out {}, grew {})",
+                    name, pool_stats.in_use, pool.capacity(), pool_stats.peak_in_use, pool_stats.exhausted, pool_stats.grown
+                ));
+                stats.push(format!("    {} taken, {} given back", pool_stats.acquired, pool_stats.released));
+            }
+        }
+        stats.push(String::new());
+
+        stats.push(format!("{} entities", world.entities().join().count()));
+        for info in &self.components {
+            let n = (info.count)(world);
+            if n > 0 {
+                stats.push(format!("  {} {}", info.name, n));
+            }
+        }
+
+        let mut inspector = vec![];
+        if let Some(ent) = self.selected {
+            if world.entities().is_alive(ent) {
+                inspector.push(format!("{:?}", ent));
+                for info in &self.components {
+                    if let Some(value) = (info.describe)(world, ent) {
+                        inspector.push(value);
+                    }
+                }
+            } else {
+                self.selected = None;
+            }
+        }
+
+        self.stats = texts(ctx, &stats, font)?;
+        self.inspector = texts(ctx, &inspector, font)?;
+        Ok(())
+    }
+
+    pub fn draw(&self, ctx: &mut Context, world: &World) -> GameResult<()> {
+        if !self.visible {
+            return Ok(());
+        }
+        let camera = world.read_resource::<Camera>();
+        let entities = world.entities();
+        let pos = world.read_storage::<Pos>();
+
+        // Hook ranges
+        graphics::set_color(ctx, graphics::Color::new(0.3, 0.6, 1.0, 0.5))?;
+        let is_hook = world.read_storage::<IsHook>();
+        let reach = world.read_resource::<PhysicsConfig>().hook_reach;
+        for (pos, _) in (&pos, &is_hook).join() {
+            let center = camera.world_to_screen(pos.0);
+            graphics::circle(ctx, graphics::DrawMode::Line(1.0), center, reach * camera.zoom, 1.0)?;
+        }
+
+        // Colliders: circles for hit boxes, boxes for tilemap collision
+        let collider = world.read_storage::<Collider>();
+        let tile_collider = world.read_storage::<TileCollider>();
+        let bullet = world.read_storage::<BulletStatus>();
+        graphics::set_color(ctx, graphics::Color::new(0.2, 1.0, 0.2, 0.8))?;
+        for (_, pos, collider, bullet) in (&*entities, &pos, &collider, bullet.maybe()).join() {
+            if let Some(BulletStatus::Dead) = bullet {
+                continue;
+            }
+            let center = camera.world_to_screen(pos.0);
+            graphics::circle(ctx, graphics::DrawMode::Line(1.0), center, collider.radius * camera.zoom, 0.5)?;
+        }
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 0.2, 0.8))?;
+        for (pos, tile_collider) in (&pos, &tile_collider).join() {
+            let half = tile_collider.0;
+            let top_left = camera.world_to_screen(Point2::new(pos.0.x - half.x, pos.0.y + half.y));
+            let size = half * 2.0 * camera.zoom;
+            let rect = graphics::Rect::new(top_left.x, top_left.y, size.x, size.y);
+            graphics::rectangle(ctx, graphics::DrawMode::Line(1.0), rect)?;
+        }
+
+        if let Some(pos) = self.selected.and_then(|ent| pos.get(ent)) {
+            graphics::set_color(ctx, graphics::Color::new(1.0, 0.3, 1.0, 1.0))?;
+            let center = camera.world_to_screen(pos.0);
+            graphics::circle(ctx, graphics::DrawMode::Line(2.0), center, PICK_RADIUS * camera.zoom, 1.0)?;
+        }
+
+        let (w, h) = (ctx.conf.window_mode.width as f32, ctx.conf.window_mode.height as f32);
+        self.draw_graph(ctx, Point2::new(10.0, h - 10.0))?;
+
+        graphics::set_color(ctx, graphics::WHITE)?;
+        draw_lines(ctx, &self.stats, Point2::new(10.0, 40.0))?;
+        let width = self.inspector.iter().map(|text| text.width()).max().unwrap_or(0) as f32;
+        draw_lines(ctx, &self.inspector, Point2::new(w - width - 10.0, 40.0))
+    }
+
+    /// Draws the frame time graph with its bottom left corner at `origin`.
+    /// The line is 60 FPS; anything over it was a slow frame.
+    fn draw_graph(&self, ctx: &mut Context, origin: Point2) -> GameResult<()> {
+        // Pixels per millisecond
+        const SCALE: f32 = 2.0;
+        const TARGET: f32 = 1000.0 / 60.0;
+
+        for (i, &seconds) in self.frame_times.iter().enumerate() {
+            let ms = seconds * 1000.0;
+            let color = if ms > TARGET * 1.5 {
+                graphics::Color::new(1.0, 0.2, 0.2, 0.8)
+            } else {
+                graphics::Color::new(0.2, 1.0, 0.2, 0.8)
+            };
+            graphics::set_color(ctx, color)?;
+            let height = (ms * SCALE).min(100.0);
+            let rect = graphics::Rect::new(origin.x + i as f32 * 2.0, origin.y - height, 2.0, height);
+            graphics::rectangle(ctx, graphics::DrawMode::Fill, rect)?;
+        }
+
+        graphics::set_color(ctx, graphics::WHITE)?;
+        let y = origin.y - TARGET * SCALE;
+        let end = origin.x + GRAPH_FRAMES as f32 * 2.0;
+        graphics::line(ctx, &[Point2::new(origin.x, y), Point2::new(end, y)], 1.0)
+    }
+}
+
+fn texts(ctx: &mut Context, lines: &[String], font: &graphics::Font) -> GameResult<Vec<graphics::Text>> {
+    // Text refuses to render an empty string, so blank lines get a space
+    lines.iter()
+        .map(|line| graphics::Text::new(ctx, if line.is_empty() { " " } else { line }, font))
+        .collect()
+}
+
+fn draw_lines(ctx: &mut Context, lines: &[graphics::Text], top_left: Point2) -> GameResult<()> {
+    for (i, text) in lines.iter().enumerate() {
+        graphics::draw(ctx, text, Point2::new(top_left.x, top_left.y + 20.0 * i as f32), 0.0)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,247 @@
+//! Level files.
+//!
+//! A level is a RON file describing the tile grid and where everything
+//! starts out. For example:
+//!
+//! ```text
+//! Level(
+//!     tile_size: 32.0,
+//!     origin: (-320.0, -192.0),
+//!     tiles: [
+//!         "#......#",
+//!         "#..==..#",
+//!         "########",
+//!     ],
+//!     player: (0.0, 0.0),
+//!     hooks: [(0.0, 0.0)],
+//!     enemies: [(kind: Walker, pos: (64.0, -144.0))],
+//!     pickups: [(weapon: "shotgun", pos: (-64.0, -144.0))],
+//!     boss: Some((200.0, -144.0)),
+//! )
+//! ```
+//!
+//! `tiles` is drawn top row first, the way it looks on screen: `#` is solid,
+//! `=` is a one-way platform and `.` is empty. `origin` is the world position
+//! of the bottom-left corner of the grid. Positions are in world coordinates.
+
+use ggez::graphics::Point2;
+use ron;
+use std::fmt;
+
+use tilemap::{Tile, Tilemap};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum EnemyKind {
+    /// Walks back and forth, turning around at walls and ledges
+    Walker,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnemySpawn {
+    pub kind: EnemyKind,
+    pub pos: (f32, f32),
+}
+
+/// A gun lying in the level, named as in the weapons file
+#[derive(Clone, Debug, Deserialize)]
+pub struct PickupSpawn {
+    pub weapon: String,
+    pub pos: (f32, f32),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Level {
+    pub tile_size: f32,
+    pub origin: (f32, f32),
+    pub tiles: Vec<String>,
+    pub player: (f32, f32),
+    #[serde(default)]
+    pub hooks: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub enemies: Vec<EnemySpawn>,
+    #[serde(default)]
+    pub pickups: Vec<PickupSpawn>,
+    #[serde(default)]
+    pub boss: Option<(f32, f32)>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    /// The file isn't valid RON, or doesn't have the shape of a Level
+    Parse { line: usize, col: usize, message: String },
+    /// The file parsed, but doesn't make sense as a level
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LevelError::Parse { line, col, ref message } => {
+                write!(f, "line {}, column {}: {}", line, col, message)
+            }
+            LevelError::Invalid { line, ref message } => {
+                write!(f, "line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Level {
+    pub fn parse(source: &str) -> Result<Level, LevelError> {
+        let level: Level = ron::de::from_str(source).map_err(|e| LevelError::Parse {
+            line: e.position.line,
+            col: e.position.col,
+            message: e.code.to_string(),
+        })?;
+        level.validate(source)?;
+        Ok(level)
+    }
+
+    fn validate(&self, source: &str) -> Result<(), LevelError> {
+        let tiles_line = find_line(source, "tiles", 1).unwrap_or(1);
+
+        if self.tile_size <= 0.0 {
+            let line = find_line(source, "tile_size", 1).unwrap_or(1);
+            return Err(LevelError::Invalid {
+                line,
+                message: format!("tile_size must be positive, not {}", self.tile_size),
+            });
+        }
+        if self.tiles.is_empty() {
+            return Err(LevelError::Invalid {
+                line: tiles_line,
+                message: "the level has no tiles".to_owned(),
+            });
+        }
+
+        let width = self.tiles[0].chars().count();
+        let mut line = tiles_line;
+        for row in &self.tiles {
+            line = find_line(source, &format!("\"{}\"", row), line).unwrap_or(line);
+            let row_width = row.chars().count();
+            if row_width != width {
+                return Err(LevelError::Invalid {
+                    line,
+                    message: format!("row is {} tiles wide, but the first row is {}", row_width, width),
+                });
+            }
+            if let Some(c) = row.chars().find(|&c| tile_from_char(c).is_none()) {
+                return Err(LevelError::Invalid {
+                    line,
+                    message: format!("unknown tile {:?} (expected '#', '=' or '.')", c),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn tilemap(&self) -> Tilemap {
+        let width = self.tiles[0].chars().count();
+        let height = self.tiles.len();
+        let origin = Point2::new(self.origin.0, self.origin.1);
+        let mut tilemap = Tilemap::new(width, height, self.tile_size, origin);
+        // The file lists rows top to bottom, but the tilemap counts up from
+        // the bottom
+        for (i, row) in self.tiles.iter().enumerate() {
+            for (col, c) in row.chars().enumerate() {
+                tilemap.set(col, height - 1 - i, tile_from_char(c).unwrap());
+            }
+        }
+        tilemap
+    }
+}
+
+fn tile_from_char(c: char) -> Option<Tile> {
+    match c {
+        '#' => Some(Tile::Solid),
+        '=' => Some(Tile::Platform),
+        '.' => Some(Tile::Empty),
+        _ => None,
+    }
+}
+
+/// The 1-based number of the first line at or after `start` containing
+/// `needle`
+fn find_line(source: &str, needle: &str, start: usize) -> Option<usize> {
+    source.lines()
+        .enumerate()
+        .skip(start - 1)
+        .find(|&(_, line)| line.contains(needle))
+        .map(|(i, _)| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_line(source: &str) -> usize {
+        match Level::parse(source).unwrap_err() {
+            LevelError::Parse { line, .. } => line,
+            LevelError::Invalid { line, .. } => line,
+        }
+    }
+
+    #[test]
+    fn parses_the_shipped_level() {
+        let source = include_str!("../resources/levels/arena.ron");
+        let level = Level::parse(source).unwrap();
+        let tilemap = level.tilemap();
+        assert_eq!((tilemap.width(), tilemap.height()), (20, 12));
+        // The last row of the file is the bottom of the map
+        assert_eq!(tilemap.get(1, 0), Tile::Solid);
+        assert_eq!(tilemap.get(1, 11), Tile::Empty);
+        assert_eq!(tilemap.get(2, 3), Tile::Platform);
+    }
+
+    #[test]
+    fn reports_the_line_of_a_ragged_row() {
+        let source = r#"Level(
+    tile_size: 32.0,
+    origin: (0.0, 0.0),
+    tiles: [
+        "#..#",
+        "#.#",
+        "####",
+    ],
+    player: (0.0, 0.0),
+)"#;
+        assert_eq!(error_line(source), 6);
+    }
+
+    #[test]
+    fn reports_the_line_of_an_unknown_tile() {
+        let source = r#"Level(
+    tile_size: 32.0,
+    origin: (0.0, 0.0),
+    tiles: [
+        "#..#",
+        "####",
+        "#xx#",
+    ],
+    player: (0.0, 0.0),
+)"#;
+        assert_eq!(error_line(source), 7);
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_tile_size() {
+        let source = r#"Level(
+    origin: (0.0, 0.0),
+    tile_size: -1.0,
+    tiles: ["#"],
+    player: (0.0, 0.0),
+)"#;
+        assert_eq!(error_line(source), 3);
+    }
+
+    #[test]
+    fn reports_the_line_of_a_syntax_error() {
+        let source = r#"Level(
+    tile_size: 32.0,
+    origin: (0.0, 0.0),
+    tiles: ["#"],
+    player: (0.0 0.0),
+)"#;
+        assert_eq!(error_line(source), 5);
+    }
+}
@@ -1,22 +1,84 @@
 extern crate ggez;
 extern crate rand;
+extern crate ron;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate specs;
 #[macro_use]
 extern crate specs_derive;
 
 use ggez::conf;
-use ggez::event::{self, EventHandler, Keycode, Mod};
+use ggez::event::{self, Axis, Button, EventHandler, Keycode, Mod, MouseButton};
 use ggez::graphics;
 use ggez::graphics::{FilterMode, Point2, Vector2, set_default_filter};
 use ggez::nalgebra as na;
 use ggez::timer;
-use ggez::{Context, ContextBuilder, GameResult};
+use ggez::{Context, ContextBuilder, GameError, GameResult};
 
 use specs::prelude::*;
 use std::collections::HashSet;
 use std::env;
+use std::fs;
+use std::io::{Read as IoRead, Write as IoWrite};
 use std::path;
 
+mod animation;
+mod assets;
+mod audio;
+mod bindings;
+mod camera;
+mod debug;
+mod level;
+mod menu;
+mod particles;
+mod physics;
+mod playing;
+mod pool;
+mod render;
+mod replay;
+mod save;
+mod scene;
+mod simulation;
+mod tilemap;
+mod weapon;
+
+use animation::{Animation, ClipKind, PLAYER_SHEET};
+use assets::Assets;
+use audio::{Audio, GgezBackend, Sound, Sounds, Volume};
+use bindings::Bindings;
+use camera::Camera;
+use level::{EnemyKind, Level};
+use menu::Title;
+use particles::{Effects, Emitter, Particle, ParticleRng};
+use physics::PhysicsConfig;
+use playing::Playing;
+use pool::{Growth, Pool};
+use render::{draw_ropes, Sprite, SpriteRenderer};
+use replay::Recording;
+use save::SaveFile;
+use scene::{Scene, SceneStack, Transition};
+use simulation::Simulation;
+use tilemap::{Tile, Tilemap};
+use weapon::{Arsenal, WeaponPickup, Weapons};
+
+const DEFAULT_LEVEL: &str = "/levels/arena.ron";
+
+const ASSET_MANIFEST: &str = "/assets.ron";
+
+const WEAPONS: &str = "/weapons.ron";
+
+const PHYSICS: &str = "/physics.ron";
+
+/// Shipped with the game in resources/
+const DEFAULT_BINDINGS: &str = "/default_bindings.ron";
+/// Written to the user's config directory by the rebinding screen, and
+/// preferred over the defaults when it exists
+const USER_BINDINGS: &str = "/bindings.ron";
+
+/// Written to the user's config directory by F5 and read back by F9
+const QUICKSAVE: &str = "/quicksave.ron";
+
 // Point2 already implements an equivalent trait but rust won't let me import
 // it
 trait Dist {
@@ -52,6 +114,30 @@ struct HasGravity;
 #[derive(Component, Debug)]
 struct IsJumping(bool);
 
+/// Set by RigidBodyPhysics while the entity is standing on something
+#[derive(Component, Debug)]
+struct Grounded(bool);
+
+/// The grace periods that make the player's jumps forgiving, counting down
+/// in seconds, or None once they've closed. See PhysicsConfig for how long
+/// each one lasts. A window that lasts 0 seconds is still open for the tick
+/// it opens on.
+#[derive(Component, Clone, Copy, Debug, Default, Deserialize, Serialize)]
+struct JumpWindows {
+    /// Time left to jump after leaving the ground without jumping
+    coyote: Option<f32>,
+    /// Time left for a jump press to take effect once it's possible
+    buffer: Option<f32>,
+    /// Whether the jump in progress started from the ground, so letting go
+    /// of jump can still cut it short
+    cuttable: bool,
+}
+
+/// Half-extents of the box an entity occupies when colliding with the
+/// Tilemap. Entities without one pass through the level geometry.
+#[derive(Component, Debug)]
+struct TileCollider(Vector2);
+
 impl Default for DeltaTime {
     fn default() -> Self {
         DeltaTime(0.0)
@@ -64,22 +150,65 @@ impl Default for GlobalTime {
     }
 }
 
+/// Integrates Vel into Pos and applies gravity.
+///
+/// Entities with a TileCollider are moved one axis at a time against the
+/// Tilemap. Hitting a floor of any kind lands them: IsJumping is cleared and
+/// Grounded is set.
 struct RigidBodyPhysics;
 
 impl<'a> System<'a> for RigidBodyPhysics {
     type SystemData = (Read<'a, DeltaTime>,
+                       Read<'a, PhysicsConfig>,
+                       ReadExpect<'a, Tilemap>,
                        Entities<'a>,
                        WriteStorage<'a, Pos>,
                        WriteStorage<'a, Vel>,
-                       ReadStorage<'a, HasGravity>);
+                       ReadStorage<'a, HasGravity>,
+                       ReadStorage<'a, TileCollider>,
+                       WriteStorage<'a, IsJumping>,
+                       WriteStorage<'a, Grounded>,
+                       Write<'a, Effects>);
 
-    fn run(&mut self, (dt, entities, mut pos, mut vel, has_gravity): Self::SystemData) {
+    fn run(&mut self, (dt, physics, tilemap, entities, mut pos, mut vel, has_gravity, tile_collider, mut is_jumping, mut grounded, mut effects): Self::SystemData) {
         let dt = dt.0;
         for (ent, pos, vel) in (&*entities, &mut pos, &mut vel).join() {
-            pos.0 += vel.0 * dt; // update pos
+            match tile_collider.get(ent) {
+                Some(&TileCollider(half)) => {
+                    let (x, hit_wall) = tilemap.sweep_x(pos.0, half, vel.0.x * dt);
+                    pos.0.x = x;
+                    if hit_wall {
+                        vel.0.x = 0.0;
+                    }
+
+                    let (y, hit_floor_or_ceiling) = tilemap.sweep_y(pos.0, half, vel.0.y * dt);
+                    pos.0.y = y;
+                    let landed = hit_floor_or_ceiling && vel.0.y <= 0.0;
+                    // Kick up dust on hard landings
+                    if landed && vel.0.y < -200.0 {
+                        let feet = Point2::new(pos.0.x, pos.0.y - half.y);
+                        effects.burst(&particles::LANDING_DUST, feet, std::f32::consts::FRAC_PI_2);
+                    }
+                    if hit_floor_or_ceiling {
+                        vel.0.y = 0.0;
+                    }
+
+                    if landed {
+                        if let Some(is_jumping) = is_jumping.get_mut(ent) {
+                            is_jumping.0 = false;
+                        }
+                    }
+                    if let Some(grounded) = grounded.get_mut(ent) {
+                        grounded.0 = landed;
+                    }
+                }
+                None => {
+                    pos.0 += vel.0 * dt; // update pos
+                }
+            }
 
             if has_gravity.get(ent).is_some() {
-                vel.0.y -= 500.0 * dt; // gravity
+                vel.0.y -= physics.gravity * dt;
             }
         }
     }
@@ -90,33 +219,71 @@ struct PlayerControl;
 impl<'a> System<'a> for PlayerControl {
     type SystemData = (Read<'a, InputState>,
                        Read<'a, DeltaTime>,
-                       WriteStorage<'a, Pos>,
+                       Read<'a, PhysicsConfig>,
                        WriteStorage<'a, Vel>,
                        WriteStorage<'a, Facing>,
-                       WriteStorage<'a, ShootCooldown>,
+                       WriteStorage<'a, Aim>,
+                       WriteStorage<'a, Emitter>,
                        WriteStorage<'a, IsJumping>,
-                       ReadStorage<'a, IsPlayer>);
-    fn run(&mut self, (input, dt, mut pos, mut vel, mut facing, mut cooldown, mut is_jumping, is_player): Self::SystemData) {
+                       WriteStorage<'a, JumpWindows>,
+                       ReadStorage<'a, Grounded>,
+                       ReadStorage<'a, IsPlayer>,
+                       ReadStorage<'a, SwingData_>,
+                       ReadStorage<'a, HitStun>,
+                       ReadStorage<'a, Dead>,
+                       Write<'a, Sounds>);
+    fn run(&mut self, (input, dt, physics, mut vel, mut facing, mut aim, mut emitter, mut is_jumping, mut jump_windows, grounded, is_player, swing_data, hit_stun, dead, mut sounds): Self::SystemData) {
         let dt = dt.0;
-        for (pos, vel, facing, cooldown, is_jumping, _) in (&mut pos, &mut vel, &mut facing, &mut cooldown, &mut is_jumping, &is_player).join() {
-            pos.0.x += input.xaxis * dt * 100.0;
-            
-            if pos.0.y < -150.0 {
-                pos.0.y = -150.0;
-                vel.0.y = 0.0;
-                is_jumping.0 = false;
+        for (vel, facing, aim, emitter, is_jumping, windows, grounded, _, swinging, hit_stun, _) in (&mut vel, &mut facing, &mut aim, emitter.maybe(), &mut is_jumping, &mut jump_windows, &grounded, &is_player, swing_data.maybe(), hit_stun.maybe(), !&dead).join() {
+            let stunned = hit_stun.map_or(false, |h| h.0 > 0.0);
+
+            // Standing on something keeps the coyote window open; it only
+            // starts running out once we've left the ground
+            if grounded.0 && !is_jumping.0 {
+                windows.coyote = Some(physics.coyote_time);
+            } else {
+                windows.coyote = count_down(windows.coyote, dt);
+            }
+            // Swings handle their own jumps, so a press there isn't saved up
+            if input.just_pressed.contains(&Input::JUMP) && swinging.is_none() {
+                windows.buffer = Some(physics.jump_buffer);
+            } else {
+                windows.buffer = count_down(windows.buffer, dt);
             }
 
-            if input.keys.contains(&Input::JUMP) && !is_jumping.0 {
-                vel.0.y = 300.0;
-                is_jumping.0 = true;
+            // Dust trails behind our feet while running
+            if let Some(emitter) = emitter {
+                emitter.active = grounded.0 && swinging.is_none() && !stunned && input.xaxis != 0.0;
+                emitter.direction = if input.xaxis > 0.0 { 2.6 } else { 0.5 };
             }
 
-            if cooldown.0 > 0.0 {
-                cooldown.0 -= dt;
+            // Running only ever changes Vel; RigidBodyPhysics does the
+            // moving. While swinging, the rope owns our velocity (see
+            // SwingPhysics).
+            if swinging.is_none() {
+                let run = if stunned { 0.0 } else { input.xaxis };
+                vel.0.x = physics.run_velocity(vel.0.x, run, grounded.0, dt);
             }
-            if cooldown.0 < 0.0 {
-                cooldown.0 = 0.0;
+
+            if stunned {
+                continue;
+            }
+
+            if windows.buffer.is_some() && windows.coyote.is_some() && !is_jumping.0 && swinging.is_none() {
+                vel.0.y = physics.jump_velocity;
+                is_jumping.0 = true;
+                windows.buffer = None;
+                windows.coyote = None;
+                windows.cuttable = true;
+                sounds.play(Sound::Jump);
+            }
+
+            // Letting go early turns the jump into a hop
+            if !is_jumping.0 || swinging.is_some() {
+                windows.cuttable = false;
+            } else if windows.cuttable && !input.jump {
+                vel.0.y = vel.0.y.min(physics.jump_cut_velocity);
+                windows.cuttable = false;
             }
 
             if input.xaxis < 0.0 {
@@ -124,10 +291,17 @@ impl<'a> System<'a> for PlayerControl {
             } else if input.xaxis > 0.0 {
                 std::mem::replace(facing, Facing::Right);
             }
+
+            *aim = Aim::from_input(&input, *facing, grounded.0 && swinging.is_none());
         }
     }
 }
 
+/// Takes `dt` seconds off a JumpWindows window, closing it once time runs out
+fn count_down(window: Option<f32>, dt: f32) -> Option<f32> {
+    window.map(|t| t - dt).filter(|&t| t >= 0.0)
+}
+
 #[derive(Component, Clone, Copy, Debug)]
 enum BulletStatus {
     Alive,
@@ -137,57 +311,111 @@ enum BulletStatus {
 #[derive(Component, Debug)]
 struct ShootCooldown(f32);
 
+/// Fires the player's current gun, switches guns, and culls bullets that
+/// leave the map or hit a wall.
 struct ShootBullets;
 
 impl<'a> System<'a> for ShootBullets {
     type SystemData = (Read<'a, InputState>,
+                       Entities<'a>,
+                       WriteExpect<'a, Pools>,
                        WriteStorage<'a, Pos>,
                        WriteStorage<'a, Vel>,
                        ReadStorage<'a, IsPlayer>,
-                       ReadStorage<'a, Facing>,
+                       ReadStorage<'a, Aim>,
                        WriteStorage<'a, ShootCooldown>,
+                       WriteStorage<'a, Weapons>,
                        WriteStorage<'a, BulletStatus>,
-                       Read<'a, DeltaTime>);
-    fn run(&mut self, (input, mut pos, mut vel, is_player, facing, mut cooldown, mut bullet, dt): Self::SystemData) {
+                       WriteStorage<'a, Sprite>,
+                       WriteStorage<'a, Damage>,
+                       WriteStorage<'a, Collider>,
+                       ReadStorage<'a, IsEnemyBullet>,
+                       ReadStorage<'a, HitStun>,
+                       ReadStorage<'a, Dead>,
+                       ReadExpect<'a, Tilemap>,
+                       Read<'a, DeltaTime>,
+                       Write<'a, Effects>,
+                       Write<'a, Sounds>);
+    fn run(&mut self, (input, entities, mut pools, mut pos, mut vel, is_player, aim, mut cooldown, mut weapons, mut bullet, mut sprite, mut damage, mut collider, is_enemy_bullet, hit_stun, dead, tilemap, dt, mut effects, mut sounds): Self::SystemData) {
         let dt = dt.0;
-        let shoot = input.shoot;
-
-        let mut player_pos = None;
-        let mut player_facing = None;
-        let mut player_cooldown = std::f32::INFINITY;
-        {
-            for (pos, facing, mut cooldown, _) in (&pos, &facing, &mut cooldown, &is_player).join() {
-                player_pos = Some(*pos);
-                player_facing = Some(*facing);
-                if cooldown.0 > 0.0 {
-                    cooldown.0 -= dt;
-                }
-                if cooldown.0 < 0.0 {
-                    cooldown.0 = 0.0;
-                }
-                player_cooldown = cooldown.0;
-                if cooldown.0 == 0.0 && input.shoot {
-                    cooldown.0 = 0.035;
-                }
+
+        let mut shot = None;
+        for (pos, aim, cooldown, weapons, _, hit_stun, _) in (&pos, &aim, &mut cooldown, &mut weapons, &is_player, hit_stun.maybe(), !&dead).join() {
+            if input.just_pressed.contains(&Input::SWITCH) {
+                weapons.cycle();
+            }
+            // Only the gun in hand reloads
+            weapons.current_mut().tick(dt);
+
+            if hit_stun.map_or(false, |h| h.0 > 0.0) {
+                continue;
+            }
+            cooldown.0 = (cooldown.0 - dt).max(0.0);
+            if input.shoot && cooldown.0 == 0.0 && weapons.current_mut().take_shot() {
+                let def = weapons.current().def.clone();
+                cooldown.0 = def.fire_interval;
+                shot = Some((*pos, *aim, def));
             }
         }
 
-        if let (Some(player_pos), Some(facing)) = (player_pos, player_facing) {
-            if input.shoot && player_cooldown == 0.0 {
-                for (mut pos, mut vel, mut bullet) in (&mut pos, &mut vel, &mut bullet).join() {
-                    if let BulletStatus::Dead = bullet {
-                        std::mem::replace(bullet, BulletStatus::Alive);
-                        pos.0 = player_pos.0;
-                        vel.0 = Vector2::new(600.0 * facing.to_f32(), 0.0);
-                        break;
-                    }
+        if let Some((player_pos, aim, def)) = shot {
+            effects.burst(&particles::MUZZLE_FLASH, player_pos.0 + aim.0 * 16.0, aim.0.y.atan2(aim.0.x));
+            sounds.play(Sound::Shoot);
+            for angle in def.angles() {
+                let ent = match pools.player_bullets.acquire() {
+                    Some(ent) => ent,
+                    None => break,
+                };
+                // The aim, turned by `angle`
+                let (sin, cos) = angle.sin_cos();
+                let dir = Vector2::new(aim.0.x * cos - aim.0.y * sin, aim.0.x * sin + aim.0.y * cos);
+
+                *bullet.get_mut(ent).unwrap() = BulletStatus::Alive;
+                pos.get_mut(ent).unwrap().0 = player_pos.0;
+                vel.get_mut(ent).unwrap().0 = dir * def.speed;
+                let bullet_sprite = sprite.get_mut(ent).unwrap();
+                bullet_sprite.image = def.sprite.clone();
+                bullet_sprite.scale = Vector2::new(def.sprite_scale, def.sprite_scale);
+                damage.get_mut(ent).unwrap().0 = def.damage;
+                collider.get_mut(ent).unwrap().radius = def.radius;
+            }
+        }
+
+        for (ent, pos, bullet, enemy) in (&*entities, &pos, &mut bullet, is_enemy_bullet.maybe()).join() {
+            if !tilemap.contains(pos.0) || tilemap.tile_at(pos.0) == Tile::Solid {
+                if let BulletStatus::Alive = *bullet {
+                    effects.burst(&particles::IMPACT, pos.0, 0.0);
                 }
+                kill_bullet(&mut pools, ent, bullet, enemy.is_some());
             }
         }
+    }
+}
 
-        for (mut pos, mut bullet) in (&mut pos, &mut bullet).join() {
-            if pos.0.x.abs() > 400.0 || pos.0.y.abs() > 400.0 {
-                std::mem::replace(bullet, BulletStatus::Dead);
+/// Hands guns lying around to the players who touch them
+struct CollectPickups;
+
+impl<'a> System<'a> for CollectPickups {
+    type SystemData = (Read<'a, Collisions>,
+                       Read<'a, Arsenal>,
+                       Entities<'a>,
+                       ReadStorage<'a, WeaponPickup>,
+                       WriteStorage<'a, Weapons>);
+    fn run(&mut self, (collisions, arsenal, entities, pickup, mut weapons): Self::SystemData) {
+        let mut taken = vec![];
+        for collision in &collisions.0 {
+            let name = match pickup.get(collision.entity) {
+                Some(pickup) => &pickup.0,
+                None => continue,
+            };
+            // Two players touching it on the same tick shouldn't both get it
+            if taken.contains(&collision.entity) {
+                continue;
+            }
+            if let (Some(def), Some(weapons)) = (arsenal.get(name), weapons.get_mut(collision.other)) {
+                weapons.give(def);
+                entities.delete(collision.entity).unwrap();
+                taken.push(collision.entity);
             }
         }
     }
@@ -199,14 +427,70 @@ struct IsHook;
 #[derive(Component, Debug)]
 struct IsSwingTarget;
 
-#[derive(Component, Clone, Copy, Debug)]
+#[derive(Component, Clone, Copy, Debug, Deserialize, Serialize)]
 struct SwingData_ {
     theta0: f32,
     theta: f32,
+    /// Angular velocity, in radians per second
+    omega: f32,
+    /// Rope length
     dist: f32,
     start_time: f64,
 }
 
+impl SwingData_ {
+    /// Reels the rope in or out to `dist` at time `now`, carrying on from the
+    /// current angle. Angular momentum is kept, so reeling in at the bottom
+    /// of a swing speeds it up like pumping a real one.
+    fn set_dist(&mut self, dist: f32, now: f64, physics: &PhysicsConfig) {
+        let (theta, omega) = self.state(now, physics);
+        let omega = omega * (self.dist / dist).powi(2);
+        self.dist = dist;
+        self.fit(theta, omega, now, physics);
+    }
+
+    /// Pushes the swing along in `direction` (-1 to 1, positive is right),
+    /// raising its amplitude by up to `amount` radians, but never past
+    /// horizontal. The angle carries on from where it is, so the player
+    /// doesn't jump. Pushing against the swing does nothing.
+    fn pump(&mut self, direction: f32, amount: f32, now: f64, physics: &PhysicsConfig) {
+        let (theta, omega) = self.state(now, physics);
+        if direction * omega < 0.0 {
+            return;
+        }
+        let k = physics.swing_frequency(self.dist) as f32;
+        let amplitude = (self.theta0.abs() + amount).min(1.5);
+        // Hanging still, or at the top of the arc, the push decides which
+        // way we go
+        let sign = if omega != 0.0 { omega.signum() } else { direction.signum() };
+        let omega = sign * k * (amplitude * amplitude - theta * theta).max(0.0).sqrt();
+        self.fit(theta, omega, now, physics);
+    }
+
+    /// The angle and angular velocity at time `now`
+    fn state(&self, now: f64, physics: &PhysicsConfig) -> (f32, f32) {
+        let k = physics.swing_frequency(self.dist);
+        let elapsed = now - self.start_time;
+        let theta = self.theta0 * (k * elapsed).cos() as f32;
+        let omega = -self.theta0 * (k as f32) * (k * elapsed).sin() as f32;
+        (theta, omega)
+    }
+
+    /// Starts a new swing on the current rope that passes through angle
+    /// `theta` with angular velocity `omega` at time `now`
+    fn fit(&mut self, theta: f32, omega: f32, now: f64, physics: &PhysicsConfig) {
+        let k = physics.swing_frequency(self.dist);
+        let theta0 = (theta * theta + (omega / k as f32).powi(2)).sqrt();
+        let phase = f64::from((-omega / k as f32).atan2(theta));
+
+        self.theta0 = theta0.min(1.5);
+        self.start_time = now - phase / k;
+    }
+}
+
+/// Hooks closer than this to the player are ignored
+const HOOK_MIN_DIST: f32 = 4.0;
+
 struct DoHook;
 
 impl<'a> System<'a> for DoHook {
@@ -217,18 +501,34 @@ impl<'a> System<'a> for DoHook {
                        WriteStorage<'a, SwingData_>,
                        ReadStorage<'a, IsHook>,
                        WriteStorage<'a, IsSwingTarget>,
-                       Read<'a, DeltaTime>,
-                       Read<'a, GlobalTime>);
-    fn run(&mut self, (input, entities, mut pos, is_player, mut swing_data, is_hook, mut is_target, dt, t): Self::SystemData) {
+                       WriteStorage<'a, IsJumping>,
+                       ReadStorage<'a, Aim>,
+                       ReadStorage<'a, Dead>,
+                       Read<'a, GlobalTime>,
+                       Read<'a, PhysicsConfig>,
+                       Write<'a, Effects>,
+                       Write<'a, Sounds>);
+    fn run(&mut self, (input, entities, mut pos, is_player, mut swing_data, is_hook, mut is_target, mut is_jumping, aim, dead, t, physics, mut effects, mut sounds): Self::SystemData) {
         if input.just_pressed.contains(&Input::TOOL) {
-            for (mut player_entity, _) in (&*entities, &is_player).join() {
+            for (mut player_entity, _, aim, _) in (&*entities, &is_player, &aim, !&dead).join() {
                 match swing_data.get(player_entity).cloned() {
-                    Some(sd) => {
-                        swing_data.remove(player_entity);
+                    Some(_) => {
+                        // Let go of the rope. Vel already holds the swing's
+                        // tangential velocity, so RigidBodyPhysics carries us
+                        // along from here.
+                        release_swing(&*entities, player_entity, &mut swing_data, &mut is_target);
+                        sounds.play(Sound::HookRelease);
                     }
                     None => {
-                        let hooks = (&*entities, &is_hook).join().map(|(e, h)| e).collect();
-                        self.try_hook(&mut pos, &mut is_target, &mut player_entity, &mut swing_data, hooks, t.0);
+                        let hooks = (&*entities, &is_hook).join().map(|(e, _)| e).collect();
+                        let hooked = self.try_hook(&mut pos, &mut is_target, &mut player_entity, &mut swing_data, hooks, aim.0, t.0, &physics);
+                        if let Some(hook_pos) = hooked {
+                            if let Some(is_jumping) = is_jumping.get_mut(player_entity) {
+                                is_jumping.0 = false;
+                            }
+                            effects.burst(&particles::HOOK_SPARKS, hook_pos, 0.0);
+                            sounds.play(Sound::HookAttach);
+                        }
                     }
                 }
             }
@@ -236,47 +536,206 @@ impl<'a> System<'a> for DoHook {
     }
 }
 
+/// Detaches `player` from whatever it's swinging on. The player keeps its
+/// current Vel.
+fn release_swing<'a>(
+    entities: &EntitiesRes,
+    player: Entity,
+    swing_data: &mut WriteStorage<'a, SwingData_>,
+    is_target: &mut WriteStorage<'a, IsSwingTarget>,
+) {
+    swing_data.remove(player);
+    let targets: Vec<Entity> = (entities, &*is_target).join().map(|(e, _)| e).collect();
+    for target in targets {
+        is_target.remove(target);
+    }
+}
+
 impl<'a> DoHook {
-    fn try_hook(&mut self, 
+    /// Latches `player` onto a hook within reach, preferring ones in the
+    /// direction it's aiming. Returns where the hook is, if it found one.
+    fn try_hook(&mut self,
                 pos: &mut WriteStorage<'a, Pos>,
                 is_target: &mut WriteStorage<'a, IsSwingTarget>,
                 player: &mut Entity,
                 swing_data: &mut WriteStorage<'a, SwingData_>,
                 hooks: Vec<Entity>,
-                t: f64
-    ) {
+                aim: Vector2,
+                t: f64,
+                physics: &PhysicsConfig
+    ) -> Option<Point2> {
         if let Some(player_pos) = pos.get(*player) {
-            let (ent, hook_pos, nearest_dist) = hooks.iter()
+            let in_reach: Vec<(Entity, Pos, f32)> = hooks.iter()
                 .map(|entity| {
                     let hook_pos = pos.get(*entity).unwrap();
                     let d = hook_pos.0.distance(&player_pos.0);
-                    (entity, hook_pos, d)
+                    (*entity, *hook_pos, d)
                 })
-                .min_by(|x, y| {
-                    PartialOrd::partial_cmp(&x.2, &y.2).unwrap()
+                // A hook we're standing right on has no direction to swing
+                // from, and one below us can't hold us up
+                .filter(|&(_, hook_pos, d)| {
+                    d >= HOOK_MIN_DIST && d < physics.hook_reach && hook_pos.0.y > player_pos.0.y
                 })
-                .unwrap();
-            
-            is_target.insert(*ent, IsSwingTarget);
-            if nearest_dist < 100.0 {
-                let dx = player_pos.0.x - hook_pos.0.x;
-                let dy = player_pos.0.y - hook_pos.0.y;
-                let theta0 = dx.atan2(-dy);
-                let dist = (dx * dx + dy * dy).sqrt();
-
-                swing_data.insert(*player, SwingData_ {
-                    theta0,
-                    theta: theta0,
-                    start_time: t,
-                    dist,
-                });
-                println!("Inserted swing data at dist {}", dist);
-            } 
+                .collect();
+            // Anything within 45 degrees of the aim counts as aimed at
+            let aimed_at = |&&(_, hook_pos, d): &&(Entity, Pos, f32)| {
+                (hook_pos.0 - player_pos.0).dot(&aim) / d > 0.7
+            };
+            let nearest = |x: &&(Entity, Pos, f32), y: &&(Entity, Pos, f32)| {
+                PartialOrd::partial_cmp(&x.2, &y.2).unwrap()
+            };
+            let chosen = in_reach.iter()
+                .filter(aimed_at)
+                .min_by(nearest)
+                .or_else(|| in_reach.iter().min_by(nearest));
+            let &(ent, hook_pos, _) = match chosen {
+                Some(chosen) => chosen,
+                None => return None,
+            };
+
+            is_target.insert(ent, IsSwingTarget);
+            let dx = player_pos.0.x - hook_pos.0.x;
+            let dy = player_pos.0.y - hook_pos.0.y;
+            // Past horizontal the rope would go slack, so start no higher
+            // than SwingData_::fit allows
+            let theta0 = dx.atan2(-dy).max(-1.5).min(1.5);
+            let dist = (dx * dx + dy * dy).sqrt().max(physics.min_rope).min(physics.max_rope);
+
+            swing_data.insert(*player, SwingData_ {
+                theta0,
+                theta: theta0,
+                omega: 0.0,
+                start_time: t,
+                dist,
+            });
+            return Some(hook_pos.0);
         }
+        None
     }
 }
 
-#[derive(Component, Clone, Copy, Debug)]
+/// Moves swinging players along their pendulum arc.
+///
+/// The swing is solved analytically from `theta0` and the time since the
+/// player latched on, rather than integrated, so the rope length never
+/// drifts. Vel is kept equal to the tangential velocity of the swing so that
+/// letting go flings the player in the direction they were moving.
+///
+/// Players with a TileCollider are swept against the Tilemap on the way.
+/// Running into a tile stops the swing dead where it hit, on a rope as long
+/// as it now is.
+struct SwingPhysics;
+
+impl<'a> System<'a> for SwingPhysics {
+    type SystemData = (Read<'a, InputState>,
+                       Read<'a, DeltaTime>,
+                       Read<'a, GlobalTime>,
+                       Read<'a, PhysicsConfig>,
+                       ReadExpect<'a, Tilemap>,
+                       Entities<'a>,
+                       WriteStorage<'a, Pos>,
+                       WriteStorage<'a, Vel>,
+                       WriteStorage<'a, SwingData_>,
+                       WriteStorage<'a, IsSwingTarget>,
+                       WriteStorage<'a, IsJumping>,
+                       ReadStorage<'a, TileCollider>,
+                       ReadStorage<'a, Dead>,
+                       Write<'a, Sounds>);
+    fn run(&mut self, (input, dt, t, physics, tilemap, entities, mut pos, mut vel, mut swing_data, mut is_target, mut is_jumping, tile_collider, dead, mut sounds): Self::SystemData) {
+        let dt = dt.0;
+        let target_pos = (&pos, &is_target).join().map(|(pos, _)| *pos).next();
+
+        let swinging: Vec<Entity> = (&*entities, &swing_data).join().map(|(e, _)| e).collect();
+        for ent in swinging {
+            if dead.get(ent).is_some() {
+                release_swing(&*entities, ent, &mut swing_data, &mut is_target);
+                continue;
+            }
+
+            let target_pos = match target_pos {
+                Some(target_pos) => target_pos,
+                None => {
+                    // The hook went away under us
+                    release_swing(&*entities, ent, &mut swing_data, &mut is_target);
+                    continue;
+                }
+            };
+
+            let (mut theta, mut omega, dist) = {
+                let sd = swing_data.get_mut(ent).unwrap();
+
+                // Up reels in, down pays out
+                if input.yaxis != 0.0 {
+                    let dist = (sd.dist - input.yaxis * physics.reel_speed * dt)
+                        .max(physics.min_rope)
+                        .min(physics.max_rope);
+                    if dist != sd.dist {
+                        sd.set_dist(dist, t.0, &physics);
+                    }
+                }
+
+                // Pump the swing by leaning the way it's already going
+                if input.xaxis != 0.0 {
+                    sd.pump(input.xaxis, input.xaxis.abs() * dt, t.0, &physics);
+                }
+
+                let (theta, omega) = sd.state(t.0, &physics);
+                sd.theta = theta;
+                sd.omega = omega;
+                (sd.theta, sd.omega, sd.dist)
+            };
+
+            if let Some(pos) = pos.get_mut(ent) {
+                let x = target_pos.0.x + dist * theta.sin();
+                let y = target_pos.0.y - dist * theta.cos();
+                let blocked = match tile_collider.get(ent) {
+                    Some(&TileCollider(half)) => {
+                        let (x, hit_x) = tilemap.sweep_x(pos.0, half, x - pos.0.x);
+                        pos.0.x = x;
+                        let (y, hit_y) = tilemap.sweep_y(pos.0, half, y - pos.0.y);
+                        pos.0.y = y;
+                        hit_x || hit_y
+                    }
+                    None => {
+                        pos.0 = Point2::new(x, y);
+                        false
+                    }
+                };
+
+                if blocked {
+                    let offset = pos.0 - target_pos.0;
+                    let sd = swing_data.get_mut(ent).unwrap();
+                    sd.dist = offset.norm().max(physics.min_rope).min(physics.max_rope);
+                    sd.fit(offset.x.atan2(-offset.y), 0.0, t.0, &physics);
+                    let (new_theta, new_omega) = sd.state(t.0, &physics);
+                    sd.theta = new_theta;
+                    sd.omega = new_omega;
+                    theta = new_theta;
+                    omega = new_omega;
+                }
+            }
+            if let Some(vel) = vel.get_mut(ent) {
+                // d/dt of the position above
+                vel.0 = Vector2::new(theta.cos(), theta.sin()) * dist * omega;
+
+                if input.just_pressed.contains(&Input::JUMP) {
+                    vel.0.y += physics.jump_velocity;
+                }
+            }
+
+            if input.just_pressed.contains(&Input::JUMP) {
+                release_swing(&*entities, ent, &mut swing_data, &mut is_target);
+                if let Some(is_jumping) = is_jumping.get_mut(ent) {
+                    is_jumping.0 = true;
+                }
+                sounds.play(Sound::HookRelease);
+                sounds.play(Sound::Jump);
+            }
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, Deserialize, Serialize)]
 enum Facing {
     Left,
     Right,
@@ -291,62 +750,412 @@ impl Facing {
     }
 }
 
-#[derive(Debug)]
-struct Actor {
-    is_player: bool, // Currently useless since there's only one Actor
-    pos: Point2,
-    vel: Vector2,
-    facing: Facing,
-    jumping: bool, // Set on jump, cleared on landing
-    shoot_cooldown: f32, // Little timer so the gun doesn't fire every frame
-    swing_data: Option<SwingData>, // If this is Some, the player is swinging
+/// Unit vector the player is aiming along, snapped to one of eight
+/// directions. Shots fly this way and the lasso prefers hooks this way.
+#[derive(Component, Clone, Copy, Debug)]
+struct Aim(Vector2);
+
+impl Aim {
+    /// Aims where the controls point. With nothing held, or just down while
+    /// standing on the ground, that's straight ahead.
+    fn from_input(input: &InputState, facing: Facing, grounded: bool) -> Aim {
+        // Half-tilted sticks count, so diagonals are easy to hit
+        let snap = |v: f32| if v > 0.5 { 1.0 } else if v < -0.5 { -1.0 } else { 0.0 };
+        let x = snap(input.xaxis);
+        let mut y = snap(input.yaxis);
+        if grounded && y < 0.0 {
+            y = 0.0;
+        }
+        if x == 0.0 && y == 0.0 {
+            return Aim(Vector2::new(facing.to_f32(), 0.0));
+        }
+        Aim(Vector2::new(x, y).normalize())
+    }
 }
 
+#[derive(Component, Clone, Copy, Debug, Deserialize, Serialize)]
 enum BossPhase {
     Attack,
     Evade,
 }
 
-struct Boss {
-    pos: Point2,
-    vel: Vector2,
+#[derive(Component, Debug)]
+struct IsBoss;
+
+#[derive(Component, Debug)]
+struct Health {
     hp: f32,
-    facing: Facing,
-    jumping: bool,
-    phase: BossPhase,
-    phase_timer: f32,
+    max: f32,
+}
+
+/// How much health this entity takes away from whatever it collides with
+#[derive(Component, Debug)]
+struct Damage(f32);
+
+/// Seconds left until a recently hit entity can move again
+#[derive(Component, Debug)]
+struct HitStun(f32);
+
+/// Seconds left until a recently hit entity can be hurt again
+#[derive(Component, Debug)]
+struct Invulnerable(f32);
+
+/// Out of health. Dead entities aren't drawn, controlled or collided with.
+#[derive(Component, Debug)]
+struct Dead;
+
+/// Seconds spent in the current BossPhase
+#[derive(Component, Debug)]
+struct PhaseTimer(f32);
+
+/// Set while the boss is in the air from a leap, so it can shake the screen
+/// when it comes down
+#[derive(Component, Debug)]
+struct Slamming;
+
+/// Marks bullets fired by enemies, so the player's gun doesn't reuse them
+#[derive(Component, Debug)]
+struct IsEnemyBullet;
+
+/// Drives the boss fight.
+///
+/// The boss alternates between two phases every 10 seconds. While attacking
+/// it chases the player, firing fans of bullets up close and leaping at them
+/// from a distance. While evading it backs away from the player and hops over
+/// any shots headed its way.
+struct BossAI;
+
+impl<'a> System<'a> for BossAI {
+    type SystemData = (Read<'a, DeltaTime>,
+                       Write<'a, Camera>,
+                       Entities<'a>,
+                       WriteStorage<'a, Slamming>,
+                       ReadStorage<'a, Grounded>,
+                       WriteStorage<'a, Pos>,
+                       WriteStorage<'a, Vel>,
+                       WriteStorage<'a, Facing>,
+                       WriteStorage<'a, IsJumping>,
+                       WriteStorage<'a, BossPhase>,
+                       WriteStorage<'a, PhaseTimer>,
+                       WriteStorage<'a, ShootCooldown>,
+                       ReadStorage<'a, IsBoss>,
+                       ReadStorage<'a, IsPlayer>,
+                       WriteStorage<'a, BulletStatus>,
+                       ReadStorage<'a, IsEnemyBullet>,
+                       ReadStorage<'a, Dead>,
+                       ReadStorage<'a, TileCollider>,
+                       ReadExpect<'a, Tilemap>,
+                       WriteExpect<'a, Pools>,
+                       Write<'a, Sounds>);
+    fn run(&mut self, (dt, mut camera, entities, mut slamming, grounded, mut pos, mut vel, mut facing, mut is_jumping, mut phase, mut phase_timer, mut cooldown, is_boss, is_player, mut bullet, is_enemy_bullet, dead, tile_collider, tilemap, mut pools, mut sounds): Self::SystemData) {
+        let dt = dt.0;
+
+        // Nothing left to fight
+        let player_pos = match (&pos, &is_player, !&dead).join().map(|(pos, _, _)| *pos).next() {
+            Some(player_pos) => player_pos,
+            None => return,
+        };
+
+        // The player's live bullets, for deciding when to dodge
+        let incoming: Vec<(Point2, Vector2)> = (&pos, &vel, &bullet, !&is_enemy_bullet).join()
+            .filter(|&(_, _, bullet, _)| if let BulletStatus::Alive = bullet { true } else { false })
+            .map(|(pos, vel, _, _)| (pos.0, vel.0))
+            .collect();
+
+        let mut fans = vec![];
+
+        for (ent, grounded, pos, vel, facing, is_jumping, phase, phase_timer, cooldown, tile_collider, _, _) in (&*entities, &grounded, &mut pos, &mut vel, &mut facing, &mut is_jumping, &mut phase, &mut phase_timer, &mut cooldown, &tile_collider, &is_boss, !&dead).join() {
+            let half = tile_collider.0;
+
+            if grounded.0 && slamming.get(ent).is_some() {
+                slamming.remove(ent);
+                camera.add_trauma(0.6);
+            }
+
+            // Friction, so knockback doesn't last forever. Skipped in the air
+            // so leaps actually go somewhere.
+            if !is_jumping.0 {
+                vel.0.x -= 10.0 * dt * vel.0.x;
+            }
+
+            if cooldown.0 > 0.0 {
+                cooldown.0 -= dt;
+            }
+            if cooldown.0 < 0.0 {
+                cooldown.0 = 0.0;
+            }
+
+            phase_timer.0 += dt;
+            match *phase {
+                BossPhase::Attack if phase_timer.0 > 10.0 => {
+                    phase_timer.0 = 0.0;
+                    *phase = BossPhase::Evade;
+                    sounds.play(Sound::BossPhase);
+                }
+                BossPhase::Evade if phase_timer.0 > 10.0 => {
+                    phase_timer.0 = 0.0;
+                    *phase = BossPhase::Attack;
+                    sounds.play(Sound::BossPhase);
+                }
+                _ => ()
+            }
+
+            let dx = player_pos.0.x - pos.0.x;
+            let direction = dx.signum();
+            if direction > 0.0 {
+                *facing = Facing::Right;
+            } else {
+                *facing = Facing::Left;
+            }
+
+            match *phase {
+                BossPhase::Attack => {
+                    if dx.abs() > 5.0 { // Chase player
+                        pos.0.x = tilemap.sweep_x(pos.0, half, direction * 100.0 * dt).0;
+                    }
+
+                    if cooldown.0 == 0.0 && !is_jumping.0 {
+                        if dx.abs() > 150.0 {
+                            // Too far away to shoot at, so jump at them
+                            vel.0 = Vector2::new(direction * 250.0, 350.0);
+                            is_jumping.0 = true;
+                            cooldown.0 = 1.5;
+                            slamming.insert(ent, Slamming);
+                        } else {
+                            let aim = player_pos.0 - pos.0;
+                            fans.push((*pos, aim.y.atan2(aim.x)));
+                            cooldown.0 = 0.8;
+                        }
+                    }
+                }
+                BossPhase::Evade => {
+                    // Back off until we hit a wall
+                    pos.0.x = tilemap.sweep_x(pos.0, half, -direction * 120.0 * dt).0;
+
+                    let threatened = incoming.iter().any(|&(bullet_pos, bullet_vel)| {
+                        let gap = pos.0.x - bullet_pos.x;
+                        let approaching = gap * bullet_vel.x > 0.0;
+                        approaching && gap.abs() < 80.0 && (bullet_pos.y - pos.0.y).abs() < 20.0
+                    });
+                    if threatened && !is_jumping.0 {
+                        vel.0.y = 350.0;
+                        is_jumping.0 = true;
+                    }
+                }
+            }
+        }
+
+        for (origin, angle) in fans {
+            fire_fan(&mut pools, &mut pos, &mut vel, &mut bullet, origin, angle);
+        }
+    }
 }
 
-fn get_time(ctx: &Context) -> f64 {
-    timer::duration_to_f64(
-        timer::get_time_since_start(ctx)
-    )
+/// Fires five enemy bullets spread evenly around `angle` (radians, measured
+/// from the positive x axis).
+fn fire_fan<'a>(
+    pools: &mut Pools,
+    pos: &mut WriteStorage<'a, Pos>,
+    vel: &mut WriteStorage<'a, Vel>,
+    bullet: &mut WriteStorage<'a, BulletStatus>,
+    origin: Pos,
+    angle: f32,
+) {
+    let count = 5;
+    let spread = 0.15;
+    for i in 0..count {
+        let ent = match pools.enemy_bullets.acquire() {
+            Some(ent) => ent,
+            None => return,
+        };
+        let theta = angle + spread * (i as f32 - (count - 1) as f32 / 2.0);
+        *bullet.get_mut(ent).unwrap() = BulletStatus::Alive;
+        pos.get_mut(ent).unwrap().0 = origin.0;
+        vel.get_mut(ent).unwrap().0 = Vector2::new(theta.cos(), theta.sin()) * 300.0;
+    }
 }
 
-#[derive(Debug)]
-struct SwingData {
-    theta0: f32,
-    theta: f32,
-    dist: f32,
-    start_time: f64,
-    target: Hook,
+/// Walks back and forth at `speed`, turning around at walls and ledges
+#[derive(Component, Debug)]
+struct Patrol {
+    speed: f32,
 }
 
-#[derive(Debug)]
-struct Bullet {
-    pos: Point2,
-    vel: Vector2,
-    alive: bool,
+struct PatrolAI;
+
+impl<'a> System<'a> for PatrolAI {
+    type SystemData = (Read<'a, DeltaTime>,
+                       ReadExpect<'a, Tilemap>,
+                       WriteStorage<'a, Pos>,
+                       WriteStorage<'a, Vel>,
+                       WriteStorage<'a, Facing>,
+                       ReadStorage<'a, Patrol>,
+                       ReadStorage<'a, TileCollider>,
+                       ReadStorage<'a, Grounded>,
+                       ReadStorage<'a, Dead>);
+    fn run(&mut self, (dt, tilemap, mut pos, mut vel, mut facing, patrol, tile_collider, grounded, dead): Self::SystemData) {
+        let dt = dt.0;
+        for (pos, vel, facing, patrol, tile_collider, grounded, _) in (&mut pos, &mut vel, &mut facing, &patrol, &tile_collider, &grounded, !&dead).join() {
+            if !grounded.0 {
+                continue;
+            }
+            // Friction, so knockback doesn't last forever
+            vel.0.x -= 10.0 * dt * vel.0.x;
+
+            let half = tile_collider.0;
+            let direction = facing.to_f32();
+            let (x, hit_wall) = tilemap.sweep_x(pos.0, half, direction * patrol.speed * dt);
+            pos.0.x = x;
+
+            // Look just below our leading edge for something to stand on
+            let ahead = Point2::new(x + direction * half.x, pos.0.y - half.y - 1.0);
+            let at_ledge = tilemap.tile_at(ahead) == Tile::Empty;
+
+            if hit_wall || at_ledge {
+                *facing = match *facing {
+                    Facing::Left => Facing::Right,
+                    Facing::Right => Facing::Left,
+                };
+            }
+        }
+    }
+}
+
+/// Spawns requested bursts and continuous emitters' particles, and ages,
+/// fades and eventually recycles live particles.
+struct ParticleSystem;
+
+impl<'a> System<'a> for ParticleSystem {
+    type SystemData = (Read<'a, DeltaTime>,
+                       Write<'a, Effects>,
+                       Write<'a, ParticleRng>,
+                       WriteExpect<'a, Pools>,
+                       Entities<'a>,
+                       WriteStorage<'a, Particle>,
+                       WriteStorage<'a, Emitter>,
+                       WriteStorage<'a, Pos>,
+                       WriteStorage<'a, Vel>,
+                       WriteStorage<'a, Sprite>,
+                       WriteStorage<'a, HasGravity>);
+    fn run(&mut self, (dt, mut effects, mut rng, mut pools, entities, mut particle, mut emitter, mut pos, mut vel, mut sprite, mut has_gravity): Self::SystemData) {
+        let dt = dt.0;
+
+        let mut expired = vec![];
+        for (ent, particle, sprite) in (&*entities, &mut particle, &mut sprite).join() {
+            let def = match particle.def {
+                Some(def) => def,
+                None => continue,
+            };
+            particle.age += dt;
+            if particle.age >= particle.lifetime {
+                expired.push(ent);
+                continue;
+            }
+            let t = particle.progress();
+            sprite.color = particles::lerp_color(def.start_color, def.end_color, t);
+            let scale = def.start_scale + (def.end_scale - def.start_scale) * t;
+            sprite.scale = Vector2::new(scale, scale);
+        }
+        for ent in expired {
+            particle.get_mut(ent).unwrap().def = None;
+            sprite.get_mut(ent).unwrap().visible = false;
+            vel.get_mut(ent).unwrap().0 = na::zero();
+            has_gravity.remove(ent);
+            pools.particles.release(ent);
+        }
+
+        let mut bursts: Vec<(&'static particles::EmitterDef, Point2, f32, u32)> = effects.0.drain(..)
+            .map(|burst| (burst.def, burst.pos, burst.direction, burst.def.count))
+            .collect();
+        for (emitter, pos) in (&mut emitter, &pos).join() {
+            if !emitter.active {
+                emitter.owed = 0.0;
+                continue;
+            }
+            emitter.owed += emitter.def.rate * dt;
+            let count = emitter.owed.floor();
+            emitter.owed -= count;
+            if count > 0.0 {
+                bursts.push((emitter.def, pos.0, emitter.direction, count as u32));
+            }
+        }
+
+        for (def, origin, direction, count) in bursts {
+            for _ in 0..count {
+                let ent = match pools.particles.acquire() {
+                    Some(ent) => ent,
+                    None => return,
+                };
+                let angle = direction + rng.between((-def.spread / 2.0, def.spread / 2.0));
+                let speed = rng.between(def.speed);
+
+                *particle.get_mut(ent).unwrap() = Particle {
+                    def: Some(def),
+                    age: 0.0,
+                    lifetime: rng.between(def.lifetime),
+                };
+                pos.get_mut(ent).unwrap().0 = origin;
+                vel.get_mut(ent).unwrap().0 = Vector2::new(angle.cos(), angle.sin()) * speed;
+                let sprite = sprite.get_mut(ent).unwrap();
+                sprite.visible = true;
+                sprite.color = def.start_color;
+                sprite.scale = Vector2::new(def.start_scale, def.start_scale);
+                if def.gravity {
+                    has_gravity.insert(ent, HasGravity);
+                }
+            }
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Hook {
-    pos: Point2
+/// Points the Camera at the player
+struct CameraFollow;
+
+impl<'a> System<'a> for CameraFollow {
+    type SystemData = (Read<'a, DeltaTime>,
+                       Write<'a, Camera>,
+                       ReadStorage<'a, Pos>,
+                       ReadStorage<'a, IsPlayer>);
+    fn run(&mut self, (dt, mut camera, pos, is_player): Self::SystemData) {
+        let target = (&pos, &is_player).join().map(|(pos, _)| pos.0).next();
+        camera.update(target, dt.0);
+    }
 }
 
-#[derive(Debug)]
-struct Bullets {
-    bullets: Vec<Bullet>,
+/// Picks the clip each animated entity should be playing and moves it along
+struct AnimationSystem;
+
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (Read<'a, DeltaTime>,
+                       WriteStorage<'a, Animation>,
+                       ReadStorage<'a, Vel>,
+                       ReadStorage<'a, IsJumping>,
+                       ReadStorage<'a, Grounded>,
+                       ReadStorage<'a, SwingData_>,
+                       ReadStorage<'a, ShootCooldown>);
+    fn run(&mut self, (dt, mut animation, vel, is_jumping, grounded, swing_data, cooldown): Self::SystemData) {
+        for (animation, vel, is_jumping, grounded, swing_data, cooldown) in (&mut animation, &vel, is_jumping.maybe(), grounded.maybe(), swing_data.maybe(), cooldown.maybe()).join() {
+            let airborne = is_jumping.map_or(false, |j| j.0) || grounded.map_or(false, |g| !g.0);
+            // A cooldown only runs right after a shot
+            let shooting = cooldown.map_or(false, |c| c.0 > 0.0);
+
+            let clip = if swing_data.is_some() {
+                ClipKind::Swing
+            } else if airborne && vel.0.y > 0.0 {
+                ClipKind::Jump
+            } else if airborne {
+                ClipKind::Fall
+            } else if shooting {
+                ClipKind::Shoot
+            } else if vel.0.x.abs() > 10.0 {
+                ClipKind::Run
+            } else {
+                ClipKind::Idle
+            };
+            animation.play(clip);
+            animation.advance(dt.0);
+        }
+    }
 }
 
 // Why does this function floor and add 0.5?
@@ -360,195 +1169,217 @@ fn quantize(pos: Point2) -> Point2 {
     Point2::new(pos.x.floor() + 0.5, pos.y.floor() + 0.5)
 }
 
-fn draw_actor(
-    assets: &mut Assets,
+fn draw_tilemap(
     ctx: &mut Context,
-    actor: &Actor,
-    screen_width: u32,
-    screen_height: u32,
+    tilemap: &Tilemap,
+    camera: &Camera,
 ) -> GameResult<()> {
-    let pos = world_to_screen_coords(screen_width, screen_height, actor.pos);
-    let image = assets.actor_image(actor);
-    let draw_params = graphics::DrawParam {
-        dest: quantize(pos),
-        rotation: 0.0,
-        offset: graphics::Point2::new(0.5, 0.5),
-        ..Default::default()
-    };
-    graphics::draw_ex(ctx, image, draw_params)?;
-
-    // Draw lasso
-    if let Some(ref sd) = actor.swing_data {
-        let target_pos = world_to_screen_coords(screen_width, screen_height, sd.target.pos);
-        graphics::line(ctx, &[pos, target_pos], 1.0)?;
+    let size = tilemap.tile_size * camera.zoom;
+    for row in 0..tilemap.height() as i32 {
+        for col in 0..tilemap.width() as i32 {
+            // Rects are positioned by their top-left corner
+            let corner = tilemap.tile_corner(col, row + 1);
+            let top_left = camera.world_to_screen(corner);
+            let rect = match tilemap.get(col, row) {
+                Tile::Empty => continue,
+                Tile::Solid => graphics::Rect::new(top_left.x, top_left.y, size, size),
+                Tile::Platform => graphics::Rect::new(top_left.x, top_left.y, size, 4.0 * camera.zoom),
+            };
+            graphics::rectangle(ctx, graphics::DrawMode::Fill, rect)?;
+        }
     }
     Ok(())
 }
 
-fn draw_debug_sprite(
-    assets: &mut Assets,
-    ctx: &mut Context,
-    pos: Pos,
-    screen_width: u32,
-    screen_height: u32,
-) -> GameResult<()> {
-    let pos = world_to_screen_coords(screen_width, screen_height, pos.0);
-    let image = &assets.player_image;
-    let draw_params = graphics::DrawParam {
-        dest: quantize(pos),
-        offset: graphics::Point2::new(0.5, 0.5),
-        ..Default::default()
-    };
-    graphics::draw_ex(ctx, image, draw_params)?;
-    Ok(())
+/// The recycled entities that short-lived things are drawn from
+struct Pools {
+    player_bullets: Pool<Entity>,
+    enemy_bullets: Pool<Entity>,
+    particles: Pool<Entity>,
 }
 
-fn draw_bullet_sprite(
-    assets: &mut Assets,
-    ctx: &mut Context,
-    pos: Pos,
-    screen_width: u32,
-    screen_height: u32,
-) -> GameResult<()> {
-    let pos = world_to_screen_coords(screen_width, screen_height, pos.0);
-    let image = &assets.bullet_image;
-    let draw_params = graphics::DrawParam {
-        dest: quantize(pos),
-        offset: graphics::Point2::new(0.5, 0.5),
-        ..Default::default()
-    };
-    graphics::draw_ex(ctx, image, draw_params)?;
-    Ok(())
+impl Pools {
+    fn new() -> Pools {
+        Pools {
+            player_bullets: Pool::new(Growth::Double { max: 800 }),
+            enemy_bullets: Pool::new(Growth::Double { max: 800 }),
+            // Running out of particles only thins out effects, so there's
+            // no need to grow
+            particles: Pool::new(Growth::Fixed),
+        }
+    }
 }
 
-fn draw_boss(
-    assets: &mut Assets,
-    ctx: &mut Context,
-    boss: &Boss,
-    screen_width: u32,
-    screen_height: u32,
-) -> GameResult<()> {
-    let pos = world_to_screen_coords(screen_width, screen_height, boss.pos);
-    let image = &assets.player_image;
-    let draw_params = graphics::DrawParam {
-        dest: quantize(pos),
-        rotation: 0.0,
-        offset: graphics::Point2::new(0.5, 0.5),
-        ..Default::default()
-    };
-    graphics::draw_ex(ctx, image, draw_params)?;
+/// Takes a live bullet out of play and gives it back to its pool
+fn kill_bullet(pools: &mut Pools, ent: Entity, bullet: &mut BulletStatus, enemy: bool) {
+    if let BulletStatus::Alive = *bullet {
+        *bullet = BulletStatus::Dead;
+        if enemy {
+            pools.enemy_bullets.release(ent);
+        } else {
+            pools.player_bullets.release(ent);
+        }
+    }
+}
 
-    Ok(())
+/// Creates a player bullet, out of play. ShootBullets sets it up for
+/// whichever gun fires it.
+fn spawn_player_bullet(world: &mut World) -> Entity {
+    world.create_entity()
+        .with(Vel(na::zero()))
+        .with(Pos(Point2::new(0.0, 0.0)))
+        .with(BulletStatus::Dead)
+        .with(Collider { radius: 5.0, layer: layer::PLAYER_BULLET, mask: layer::BOSS | layer::ENEMY })
+        .with(Damage(10.0))
+        .with(Sprite::new("bullet", render::z::BULLET))
+        .build()
 }
 
-fn draw_bullets(
-    assets: &mut Assets,
-    ctx: &mut Context,
-    bullets: &Bullets,
-    screen_width: u32,
-    screen_height: u32
-) -> GameResult<()> {
-    let image = &assets.bullet_image;
-    for bullet in &bullets.bullets {
-        if bullet.alive {
-            let pos = world_to_screen_coords(screen_width, screen_height, bullet.pos);
-            let draw_params = graphics::DrawParam {
-                dest: quantize(pos),
-                rotation: 0.0,
-                offset: graphics::Point2::new(0.5, 0.5),
-                ..Default::default()
-            };
-            graphics::draw_ex(ctx, image, draw_params)?;
-        }
-    }
-    Ok(())
+fn spawn_enemy_bullet(world: &mut World) -> Entity {
+    world.create_entity()
+        .with(Vel(na::zero()))
+        .with(Pos(Point2::new(0.0, 0.0)))
+        .with(BulletStatus::Dead)
+        .with(IsEnemyBullet)
+        .with(Collider { radius: 5.0, layer: layer::ENEMY_BULLET, mask: layer::PLAYER })
+        .with(Damage(10.0))
+        .with(Sprite::new("bullet", render::z::BULLET))
+        .build()
 }
 
-fn draw_hook(
-    assets: &mut Assets,
-    ctx: &mut Context,
-    hook: Hook,
-    screen_width: u32,
-    screen_height: u32
-) -> GameResult<()> {
-    let image = &assets.hook_image;
-    let pos = world_to_screen_coords(screen_width, screen_height, hook.pos);
-    let draw_params = graphics::DrawParam {
-        dest: quantize(pos),
-        rotation: 0.0,
-        offset: graphics::Point2::new(0.5, 0.5),
-        ..Default::default()
-    };
-    graphics::draw_ex(ctx, image, draw_params)
-}
-
-fn create_player() -> Actor {
-    Actor {
-        is_player: true,
-        pos: Point2::origin(),
-        vel: na::zero(),
-        facing: Facing::Right,
-        jumping: false,
-        shoot_cooldown: 0.0,
-        swing_data: None,
-    }
+/// Creates an unused particle for the particle pool
+fn spawn_particle(world: &mut World) -> Entity {
+    let mut sprite = Sprite::new("particle", render::z::PARTICLE);
+    sprite.visible = false;
+    world.create_entity()
+        .with(Vel(na::zero()))
+        .with(Pos(Point2::new(0.0, 0.0)))
+        .with(Particle::default())
+        .with(sprite)
+        .build()
+}
+
+/// Adds the level's Tilemap to the world, and a Camera looking at where the
+/// player starts
+fn add_tilemap(world: &mut World, level: &Level) {
+    let tilemap = level.tilemap();
+
+    let mut camera = Camera::default();
+    camera.bounds = Some(tilemap.bounds());
+    camera.snap_to(Point2::new(level.player.0, level.player.1));
+    world.add_resource(camera);
+    world.add_resource(tilemap);
 }
 
-fn create_bullets(n: u32) -> Bullets {
-    let mut bullets = Vec::new();
-    for _ in 0..n {
-        bullets.push(Bullet {
-            pos: Point2::new(0.0, 0.0),
-            vel: Vector2::new(0.0, 0.0),
-            alive: false,
-        });
+/// Spawns everything the level places
+fn spawn_level(world: &mut World, level: &Level) {
+    spawn_player(world, Point2::new(level.player.0, level.player.1));
+    for &(x, y) in &level.hooks {
+        spawn_hook(world, Point2::new(x, y));
+    }
+    for enemy in &level.enemies {
+        let pos = Point2::new(enemy.pos.0, enemy.pos.1);
+        match enemy.kind {
+            EnemyKind::Walker => spawn_walker(world, pos),
+        }
+    }
+    for pickup in &level.pickups {
+        let pos = Point2::new(pickup.pos.0, pickup.pos.1);
+        spawn_pickup(world, &pickup.weapon, pos);
     }
-    Bullets { bullets }
-}
-
-fn create_boss() -> Boss {
-    Boss {
-        pos: Point2::origin(),
-        vel: na::zero(),
-        hp: 50.0,
-        facing: Facing::Left,
-        jumping: false,
-        phase: BossPhase::Attack,
-        phase_timer: 0.0,
+    if let Some((x, y)) = level.boss {
+        spawn_boss(world, Point2::new(x, y));
     }
 }
 
-fn create_hook(pos: Point2) -> Hook {
-    Hook {
-        pos
-    }
+fn spawn_player(world: &mut World, pos: Point2) -> Entity {
+    let weapons = Weapons::new(world.read_resource::<Arsenal>().starting_weapon());
+    world.create_entity()
+        .with(Vel(na::zero()))
+        .with(Pos(pos))
+        .with(Facing::Right)
+        .with(Aim(Vector2::new(1.0, 0.0)))
+        .with(Emitter::new(&particles::RUN_DUST, 2.6))
+        .with(Animation::new(&PLAYER_SHEET))
+        .with(Sprite::new("player_sheet", render::z::PLAYER))
+        .with(IsPlayer)
+        .with(HasGravity)
+        .with(IsJumping(false))
+        .with(JumpWindows::default())
+        .with(Grounded(false))
+        .with(TileCollider(Vector2::new(16.0, 16.0)))
+        .with(ShootCooldown(0.0))
+        .with(weapons)
+        .with(Collider { radius: 8.0, layer: layer::PLAYER, mask: 0 })
+        .with(Health { hp: 50.0, max: 50.0 })
+        .with(HitStun(0.0))
+        .with(Invulnerable(0.0))
+        .build()
 }
 
-struct Assets {
-    player_image: graphics::Image,
-    bullet_image: graphics::Image,
-    hook_image: graphics::Image,
-    font: graphics::Font,
+/// Spawns the gun `weapon` lying on the ground. Returns None if the weapons
+/// file has no such gun.
+fn spawn_pickup(world: &mut World, weapon: &str, pos: Point2) -> Option<Entity> {
+    let sprite = match world.read_resource::<Arsenal>().get(weapon) {
+        Some(def) => {
+            let mut sprite = Sprite::new(&def.sprite, render::z::PICKUP);
+            sprite.scale = Vector2::new(def.sprite_scale, def.sprite_scale);
+            sprite
+        }
+        None => {
+            println!("Skipping pickup: there's no weapon named {:?}", weapon);
+            return None;
+        }
+    };
+    Some(world.create_entity()
+        .with(Pos(pos))
+        .with(WeaponPickup(weapon.to_owned()))
+        .with(sprite)
+        .with(Collider { radius: 12.0, layer: layer::PICKUP, mask: layer::PLAYER })
+        .build())
 }
 
-impl Assets {
-    fn new(ctx: &mut Context) -> GameResult<Assets> {
-        let player_image = graphics::Image::new(ctx, "/player.png")?;
-        let bullet_image = graphics::Image::new(ctx, "/big_bullet.png")?;
-        let hook_image = graphics::Image::new(ctx, "/big_bullet.png")?;
-        let font = graphics::Font::new(ctx, "/Roboto-Regular.ttf", 18)?;
+fn spawn_hook(world: &mut World, pos: Point2) -> Entity {
+    world.create_entity()
+        .with(Pos(pos))
+        .with(IsHook)
+        .with(Sprite::new("hook", render::z::HOOK))
+        .build()
+}
 
-        Ok(Assets {
-            player_image,
-            bullet_image,
-            hook_image,
-            font,
-         })
-    }
+fn spawn_walker(world: &mut World, pos: Point2) -> Entity {
+    world.create_entity()
+        .with(Vel(na::zero()))
+        .with(Pos(pos))
+        .with(Facing::Left)
+        .with(HasGravity)
+        .with(Grounded(false))
+        .with(TileCollider(Vector2::new(16.0, 16.0)))
+        .with(Patrol { speed: 60.0 })
+        .with(Sprite::new("player", render::z::ENEMY))
+        .with(Health { hp: 30.0, max: 30.0 })
+        .with(Collider { radius: 12.0, layer: layer::ENEMY, mask: layer::PLAYER })
+        .with(Damage(10.0))
+        .build()
+}
 
-    fn actor_image(&mut self, _: &Actor) -> &mut graphics::Image {
-        &mut self.player_image
-    }
+fn spawn_boss(world: &mut World, pos: Point2) -> Entity {
+    world.create_entity()
+        .with(Vel(na::zero()))
+        .with(Pos(pos))
+        .with(Facing::Left)
+        .with(IsBoss)
+        .with(Sprite::new("player", render::z::ENEMY))
+        .with(HasGravity)
+        .with(IsJumping(false))
+        .with(Grounded(false))
+        .with(TileCollider(Vector2::new(16.0, 16.0)))
+        .with(Health { hp: 50.0, max: 50.0 })
+        .with(BossPhase::Attack)
+        .with(PhaseTimer(0.0))
+        .with(ShootCooldown(1.0))
+        .with(Collider { radius: 10.0, layer: layer::BOSS, mask: 0 })
+        .build()
 }
 
 #[derive(Debug)]
@@ -560,6 +1391,10 @@ struct InputState {
     tool: bool,
     keys: HashSet<Input>,
     just_pressed: HashSet<Input>,
+    /// Analog stick position, from -1 to 1 on each axis with up positive.
+    /// Only ever holds multiples of 1/127, so replays can store it exactly.
+    stick_x: f32,
+    stick_y: f32,
 }
 
 impl InputState {
@@ -569,17 +1404,73 @@ impl InputState {
         }
         self.keys.insert(input);
     }
+
+    fn unregister_keypress(&mut self, input: Input) {
+        self.keys.remove(&input);
+    }
+
+    /// Moves the analog stick. Either axis can be left alone with None.
+    fn set_stick(&mut self, x: Option<f32>, y: Option<f32>) {
+        let quantize = |v: f32| (v.max(-1.0).min(1.0) * 127.0).round() / 127.0;
+        if let Some(x) = x {
+            self.stick_x = quantize(x);
+        }
+        if let Some(y) = y {
+            self.stick_y = quantize(y);
+        }
+    }
+
+    /// The input state contains useful (but strictly redundant) flags that
+    ///   area easier to use than just checking what inputs are pressed. This
+    ///   function updates them.
+    fn update_flags(&mut self) {
+        // true  as i32 as f32 = 1.0
+        // false as i32 as f32 = 0.0
+        // This way, simultaneously pressing both left and right does nothing.
+        //   It might be better to give precedence to whichever input was
+        //   pressed latest, e.g. if you were holding right, then began to hold
+        //   left while still holding right, the character would turn around.
+        //   Instead we just require the player to release right if they want
+        //   to turn around.
+
+        let left = self.keys.contains(&Input::LEFT) as i32 as f32;
+        let right = self.keys.contains(&Input::RIGHT) as i32 as f32;
+        self.xaxis = (-1.0 * left) + (1.0 * right);
+
+        let down = self.keys.contains(&Input::DOWN) as i32 as f32;
+        let up = self.keys.contains(&Input::UP) as i32 as f32;
+        self.yaxis = (-1.0 * down) + (1.0 * up);
+
+        // Buttons win over the stick when both are in use
+        if self.xaxis == 0.0 {
+            self.xaxis = self.stick_x;
+        }
+        if self.yaxis == 0.0 {
+            self.yaxis = self.stick_y;
+        }
+
+        self.jump = self.keys.contains(&Input::JUMP);
+        self.shoot = self.keys.contains(&Input::SHOOT);
+        self.tool = self.keys.contains(&Input::TOOL);
+    }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 enum Input {
     LEFT,
     RIGHT,
+    UP,
+    DOWN,
     JUMP,
     SHOOT,
     TOOL,
+    /// Switch to the next gun
+    SWITCH,
 }
 
+/// Every Input, in the order the rebinding screen asks for them
+const ALL_INPUTS: [Input; 8] = [Input::LEFT, Input::RIGHT, Input::UP, Input::DOWN, Input::JUMP, Input::SHOOT, Input::TOOL, Input::SWITCH];
+
 impl Default for InputState {
     fn default() -> Self {
         InputState {
@@ -590,471 +1481,546 @@ impl Default for InputState {
             tool: false,
             keys: HashSet::new(),
             just_pressed: HashSet::new(),
+            stick_x: 0.0,
+            stick_y: 0.0,
         }
     }
 }
 
-struct MainState<'a, 'b> {
-    player: Actor,
-    bullets: Bullets,
-    boss: Boss,
-    hooks: Vec<Hook>,
+/// Everything that outlives any one scene
+struct Game {
     assets: Assets,
-    screen_width: u32,
-    screen_height: u32,
-    global_time: f64,
-    debug_data: graphics::Text,
-    world: World,
-    dispatcher: Dispatcher<'a, 'b>
+    audio: Audio,
+    bindings: Bindings,
+    /// Relative to resources/, like everything in the ggez filesystem
+    level_path: String,
+    /// A recording for the first game started to play back, if there is one
+    replay_path: Option<path::PathBuf>,
+    /// Where to save each game's input recording, if we're making them
+    record_path: Option<path::PathBuf>,
+}
+
+struct MainState {
+    game: Game,
+    scenes: SceneStack,
 }
 
-impl<'a, 'b> MainState<'a, 'b> {
-    fn new(ctx: &mut Context) -> GameResult<MainState<'a, 'b>> {
+impl MainState {
+    fn new(ctx: &mut Context, resources: &path::Path, options: &Options) -> GameResult<MainState> {
         ctx.print_resource_stats();
         graphics::set_background_color(ctx, (0, 0, 0, 255).into());
 
         println!("Game resource path: {:?}", ctx.filesystem);
 
-        let assets = Assets::new(ctx)?;
-        let debug_data = graphics::Text::new(ctx, "debug", &assets.font)?;
-
-        let player = create_player();
-        let bullets = create_bullets(100);
-
-        let screen_width = ctx.conf.window_mode.width;
-        let screen_height = ctx.conf.window_mode.height;
-
-        let mut hooks = vec![];
-        for i in 0..3 {
-            let hook = create_hook(Point2::new(-150.0 + 150.0 * i as f32, 0.0));
-            hooks.push(hook);
+        let mut assets = Assets::load(ctx, ASSET_MANIFEST)?;
+        playing::read_arsenal(ctx)?.check_sprites(&assets).map_err(|e| {
+            GameError::ResourceLoadError(format!("{}: {}", WEAPONS, e))
+        })?;
+        if options.dev {
+            assets.watch(resources);
         }
 
-        let now = get_time(ctx);
-
-        let boss = create_boss();
-
-        let mut world = World::new();
-        world.register::<Pos>();
-        world.register::<Vel>();
-        world.register::<IsPlayer>();
-        world.register::<BulletStatus>();
-        world.register::<Facing>();
-        world.register::<HasGravity>();
-        world.register::<ShootCooldown>();
-        world.register::<IsJumping>();
-        world.register::<IsHook>();
-
-        // The player
-        world.create_entity()
-            .with(Vel(na::zero()))
-            .with(Pos(Point2::new(0.0, 0.0)))
-            .with(Facing::Right)
-            .with(IsPlayer)
-            .with(HasGravity)
-            .with(IsJumping(false))
-            .with(ShootCooldown(0.035))
-            .build();
-
-        for _ in 0..100 {
-            world.create_entity()
-                .with(Vel(na::zero()))
-                .with(Pos(Point2::new(0.0, 0.0)))
-                .with(BulletStatus::Dead)
-                .build();
-        }
-
-        for i in 0..3 {
-            world.create_entity()
-                .with(Pos(Point2::new(-150.0 + 150.0 * i as f32, 0.0)))
-                .with(IsHook)
-                .build();
-        }
-
-        world.add_resource(DeltaTime(0.0));
-        world.add_resource(InputState::default());
-
-        let mut dispatcher = DispatcherBuilder::new()
-            .with(RigidBodyPhysics, "rigid-body-physics", &[])
-            .with(PlayerControl, "player-control", &[])
-            .with(ShootBullets, "shoot-bullets", &[])
-            .with(DoHook, "do-hook", &[])
-            .build();
-        dispatcher.setup(&mut world.res);
+        let mut audio = if options.mute {
+            Audio::null()
+        } else {
+            match GgezBackend::new(ctx, &assets) {
+                Ok(backend) => Audio::new(Box::new(backend), Volume::default()),
+                Err(e) => {
+                    println!("Could not start audio, playing without sound: {}", e);
+                    Audio::null()
+                }
+            }
+        };
+        let mut volume = audio.volume();
+        volume.master = options.volume;
+        audio.set_volume(volume);
+        audio.play_music("level", 0.0);
 
-        let s = MainState {
-            player,
+        let mut game = Game {
             assets,
-            boss,
-            hooks,
-            bullets,
-            screen_width,
-            screen_height,
-            global_time: now,
-            debug_data,
-            world,
-            dispatcher
+            audio,
+            bindings: load_bindings(ctx),
+            level_path: options.level_path.clone(),
+            replay_path: options.replay_path.clone(),
+            record_path: options.record_path.clone(),
         };
 
-        Ok(s)
-    }
-
-    fn update_ui(&mut self, ctx: &mut Context) {
-        let debug_str = format!("Debug: {}", timer::get_fps(ctx) as i32);
-        let debug_text = graphics::Text::new(ctx, &debug_str, &self.assets.font).unwrap();
-
-        self.debug_data = debug_text;
-    }
-
-    /// The input state contains useful (but strictly redundant) flags that
-    ///   area easier to use than just checking what inputs are pressed. This
-    ///   function updates them.
-    fn update_key_flags(&mut self) {
-        // true  as i32 as f32 = 1.0
-        // false as i32 as f32 = 0.0
-        // This way, simultaneously pressing both left and right does nothing.
-        //   It might be better to give precedence to whichever input was
-        //   pressed latest, e.g. if you were holding right, then began to hold
-        //   left while still holding right, the character would turn around.
-        //   Instead we just require the player to release right if they want
-        //   to turn around.
-        
-        let mut input_state = self.world.write_resource::<InputState>();
-
-        let left = input_state.keys.contains(&Input::LEFT) as i32 as f32;
-        let right = input_state.keys.contains(&Input::RIGHT) as i32 as f32;
-        input_state.xaxis = (-1.0 * left) + (1.0 * right);
+        // Replays go straight into the game, since that's what was recorded
+        let first: Box<dyn Scene> = if game.replay_path.is_some() {
+            Box::new(Playing::new(ctx, &mut game)?)
+        } else {
+            Box::new(Title::new())
+        };
 
-        input_state.jump = input_state.keys.contains(&Input::JUMP);
-        input_state.shoot = input_state.keys.contains(&Input::SHOOT);
-        input_state.tool = input_state.keys.contains(&Input::TOOL);
+        Ok(MainState {
+            game,
+            scenes: SceneStack::new(first),
+        })
     }
 
-    fn register_keypress(&mut self, input: Input) {
-        let mut input_state = self.world.write_resource::<InputState>();
-        if !input_state.keys.contains(&input) {
-            input_state.just_pressed.insert(input);
+    fn apply(&mut self, ctx: &mut Context, transition: Transition) {
+        if !self.scenes.apply(&mut self.game, transition) {
+            ctx.quit().unwrap();
         }
-        input_state.keys.insert(input);
     }
 
-    fn unregister_keypress(&mut self, input: Input) {
-        let mut input_state = self.world.write_resource::<InputState>();
-        input_state.keys.remove(&input);
+    /// Takes every scene down, letting them save whatever they need to
+    fn shut_down(&mut self) {
+        self.scenes.clear(&mut self.game);
     }
 }
 
-/// Translates the world coordinate system, which
-/// has Y pointing up and the origin at the center,
-/// to the screen coordinate system, which has Y
-/// pointing downward and the origin at the top-left,
-fn world_to_screen_coords(screen_width: u32, screen_height: u32, point: Point2) -> Point2 {
-    let width = screen_width as f32;
-    let height = screen_height as f32;
-    let x = point.x + width / 2.0;
-    let y = height - (point.y + height / 2.0);
-    Point2::new(x, y)
+struct Disc {
+    pos: Point2,
+    radius: f32
 }
 
-/*
-fn player_handle_input(actor: &mut Actor, bullets: &mut Bullets, hooks: &[Hook], input: &InputState, dt: f32, t: f64) {
-    actor.vel.x = input.xaxis * 200.0;
-
-    if input.xaxis < 0.0 && !input.shoot {
-        actor.facing = Facing::Left;
-    } else if input.xaxis > 0.0 && !input.shoot {
-        actor.facing = Facing::Right;
-    }
-
-    if let Some(ref mut sd) = actor.swing_data {
-        sd.theta0 += input.xaxis * dt;
-    }
-
-    if input.jump && !actor.jumping {
-        actor.jumping = true;
-        actor.vel.y = 300.0;
-        actor.swing_data = None;
-    }
-
-    if input.just_pressed.contains(&Input::TOOL) {
-        // Later we should switch on the kind of tool equipped.
-        actor.swing_data = try_hook(&actor, hooks, t);
-        if actor.swing_data.is_some() {
-            actor.jumping = false;
-        }
+impl Disc {
+    fn new(pos: Point2, radius: f32) -> Self {
+        Disc { pos, radius }
     }
 
-    if input.shoot && actor.shoot_cooldown == 0.0 {
-        actor.shoot_cooldown = 0.035;
-        shoot_a_bullet(actor, bullets);
-    }
-}*/
-
-fn try_hook(actor: &Actor, hooks: &[Hook], t: f64) -> Option<SwingData> {
-    // Try to attach if we aren't hooked
-    // by finding the closest hook and checking if it's within 100 pixels
-    let (hook, nearest_dist) = hooks.iter()
-        .map(|hook| {
-            let d = hook.pos.distance(&actor.pos);
-            (hook, d)
-        })
-        .min_by(|x, y| {
-            PartialOrd::partial_cmp(&x.1, &y.1).unwrap()
-        })
-        .unwrap();
-    if nearest_dist < 100.0 {
-        let dx = actor.pos.x - hook.pos.x;
-        let dy = actor.pos.y - hook.pos.y;
-        let theta0 = dx.atan2(-dy);
-        let dist = (dx * dx + dy * dy).sqrt();
-
-        Some(SwingData {
-            theta0,
-            theta: theta0,
-            start_time: t,
-            target: *hook,
-            dist,
-        })
-    } else {
-        None
+    fn intersects(&self, other: &Disc) -> bool {
+        let d = self.pos.distance(&other.pos);
+        d < self.radius + other.radius
     }
 }
 
-fn player_update_position(actor: &mut Actor, dt: f32, t: f64) {
-    let mut sd = actor.swing_data.take();
-    if let Some(ref mut swing_data) = sd {
-        player_update_swing(actor, swing_data, t);
-    } else {
-        //player_update_walk(actor, dt);
-    }
-    //player_update_gun(actor, dt);
-    actor.swing_data = sd;
+/// Collision layers. Every Collider sits on one layer and has a mask of the
+/// layers it wants to hear about.
+mod layer {
+    pub const PLAYER: u32 = 1 << 0;
+    pub const BOSS: u32 = 1 << 1;
+    pub const PLAYER_BULLET: u32 = 1 << 2;
+    pub const ENEMY_BULLET: u32 = 1 << 3;
+    pub const ENEMY: u32 = 1 << 4;
+    pub const PICKUP: u32 = 1 << 5;
 }
 
-fn player_update_swing(actor: &mut Actor, swing_data: &mut SwingData, t: f64) {
-    let theta0 = swing_data.theta0;
-    let elapsed = t - swing_data.start_time;
-    let dist = swing_data.dist;
-    let k = 10.0 * 6.28 / f64::from(dist); // 2pi / period in seconds
-    let theta = theta0 * (k * elapsed).cos() as f32;
-    let target = swing_data.target;
-    swing_data.theta = theta;
-
-    actor.pos.x = target.pos.x + dist * theta.sin();
-    actor.pos.y = target.pos.y - dist * theta.cos();
+#[derive(Component, Clone, Copy, Debug)]
+struct Collider {
+    radius: f32,
+    layer: u32,
+    mask: u32,
+}
 
-    actor.vel.y = 0.0;
+/// `entity` touched `other`, and `other` is on a layer in `entity`'s mask.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Collision {
+    entity: Entity,
+    other: Entity,
 }
 
-fn boss_update(boss: &mut Boss, player: &mut Actor, bullets: &mut Bullets, dt: f32) {
-    let dv = boss.vel * dt;
-    boss.pos += dv;
+/// Every collision from the current tick
+#[derive(Debug, Default)]
+struct Collisions(Vec<Collision>);
 
-    let dist = player.pos.x - boss.pos.x;
+/// The bullet collisions from the current tick that BulletImpact let land.
+/// A bullet touching several things at once only hits the first of them.
+#[derive(Debug, Default)]
+struct Impacts(Vec<Collision>);
 
-    if dist.abs() > 5.0 { // Chase player
-        let direction = dist.signum();
-        if direction > 0.0 {
-            boss.facing = Facing::Right;
-        } else {
-            boss.facing = Facing::Left;
-        }
-        boss.pos.x += direction * 100.0 * dt;
-    }
+/// Finds overlapping colliders and records them in the Collisions resource.
+/// Dead bullets don't collide with anything.
+struct CollisionSystem;
 
-    if boss.pos.y < -150.0 {
-        boss.pos.y = -150.0;
-        if boss.vel.y < 0.0 {
-            boss.vel.y = 0.0;
-            boss.jumping = false;
+impl<'a> System<'a> for CollisionSystem {
+    type SystemData = (Write<'a, Collisions>,
+                       Entities<'a>,
+                       ReadStorage<'a, Pos>,
+                       ReadStorage<'a, Collider>,
+                       ReadStorage<'a, BulletStatus>,
+                       ReadStorage<'a, Dead>);
+    fn run(&mut self, (mut collisions, entities, pos, collider, bullet, dead): Self::SystemData) {
+        collisions.0.clear();
+
+        let live: Vec<(Entity, Disc, Collider)> = (&*entities, &pos, &collider, bullet.maybe(), !&dead).join()
+            .filter(|&(_, _, _, bullet, _)| match bullet {
+                Some(BulletStatus::Dead) => false,
+                _ => true,
+            })
+            .map(|(ent, pos, collider, _, _)| (ent, Disc::new(pos.0, collider.radius), *collider))
+            .collect();
+
+        for &(ent, ref disc, collider) in &live {
+            if collider.mask == 0 {
+                continue;
+            }
+            for &(other, ref other_disc, other_collider) in &live {
+                if ent != other && collider.mask & other_collider.layer != 0 && disc.intersects(other_disc) {
+                    collisions.0.push(Collision { entity: ent, other });
+                }
+            }
         }
-    } else {
-        boss.vel.y -= dt * 500.0;
     }
+}
 
-    boss.vel.x -= 10.0 * dt * boss.vel.x;
-
-    boss.phase_timer += dt;
-    match boss.phase {
-        BossPhase::Attack if boss.phase_timer > 10.0 => {
-            boss.phase_timer = 0.0;
-            boss.phase = BossPhase::Evade;
+/// Kills bullets that hit something, knocking back whatever they hit. The
+/// damage itself is dealt by DamageSystem.
+struct BulletImpact;
 
-        }
-        BossPhase::Evade if boss.phase_timer > 10.0 => {
-            boss.phase_timer = 0.0;
-            boss.phase = BossPhase::Attack;
-        }
-        _ => ()
-    }
+impl<'a> System<'a> for BulletImpact {
+    type SystemData = (Read<'a, Collisions>,
+                       Write<'a, Impacts>,
+                       WriteExpect<'a, Pools>,
+                       Write<'a, Effects>,
+                       WriteStorage<'a, BulletStatus>,
+                       ReadStorage<'a, IsEnemyBullet>,
+                       ReadStorage<'a, Pos>,
+                       WriteStorage<'a, Vel>);
+    fn run(&mut self, (collisions, mut impacts, mut pools, mut effects, mut bullet, is_enemy_bullet, pos, mut vel): Self::SystemData) {
+        impacts.0.clear();
+        for collision in &collisions.0 {
+            match bullet.get_mut(collision.entity) {
+                Some(bullet) => match *bullet {
+                    BulletStatus::Alive => {
+                        let enemy = is_enemy_bullet.get(collision.entity).is_some();
+                        kill_bullet(&mut pools, collision.entity, bullet, enemy);
+                        impacts.0.push(*collision);
+                        if let Some(pos) = pos.get(collision.entity) {
+                            effects.burst(&particles::IMPACT, pos.0, 0.0);
+                        }
+                    }
+                    // It already hit something else this tick
+                    BulletStatus::Dead => continue,
+                },
+                None => continue,
+            }
 
-    match boss.phase {
-        BossPhase::Attack => {
-            boss_update_attack(boss, player, bullets, dt);
-        }
-        BossPhase::Evade => {
-            boss_update_evade(boss, player, dt);
+            let knockback = vel.get(collision.entity).map(|v| v.0.x / 2.0).unwrap_or(0.0);
+            if let Some(vel) = vel.get_mut(collision.other) {
+                vel.0.x += knockback;
+            }
         }
     }
 }
 
-fn boss_update_attack(boss: &mut Boss, player: &mut Actor, bullets: &mut Bullets, dt: f32) {
-    // Unimplemented   
-}
+/// Applies Damage from this tick's collisions to Health, and marks entities
+/// that run out of health as Dead. Bullets only do damage with the hits in
+/// Impacts.
+///
+/// Entities with HitStun and Invulnerable get those timers reset when hit.
+/// While Invulnerable is running, further hits do nothing.
+struct DamageSystem;
 
-fn boss_update_evade(boss: &mut Boss, player: &mut Actor, dt: f32) {
-    // Unimplemented
-}
+impl<'a> System<'a> for DamageSystem {
+    type SystemData = (Read<'a, DeltaTime>,
+                       Read<'a, Collisions>,
+                       Read<'a, Impacts>,
+                       Write<'a, Camera>,
+                       ReadStorage<'a, IsPlayer>,
+                       ReadStorage<'a, BulletStatus>,
+                       ReadStorage<'a, Damage>,
+                       WriteStorage<'a, Health>,
+                       WriteStorage<'a, HitStun>,
+                       WriteStorage<'a, Invulnerable>,
+                       WriteStorage<'a, Dead>,
+                       Write<'a, Sounds>);
+    fn run(&mut self, (dt, collisions, impacts, mut camera, is_player, bullet, damage, mut health, mut hit_stun, mut invulnerable, mut dead, mut sounds): Self::SystemData) {
+        let dt = dt.0;
 
-fn handle_intersection(boss: &mut Boss, bullets: &mut Bullets, dt: f32) {
-    for bullet in &mut bullets.bullets {
-        if bullet.alive && Disc::new(bullet.pos, 5.0).intersects(&Disc::new(boss.pos, 10.0)) {
-            bullet.alive = false;
-            boss.hp -= 10.0;
-            boss.vel.x += bullet.vel.x / 2.0;
+        for hit_stun in (&mut hit_stun).join() {
+            hit_stun.0 = (hit_stun.0 - dt).max(0.0);
+        }
+        for invulnerable in (&mut invulnerable).join() {
+            invulnerable.0 = (invulnerable.0 - dt).max(0.0);
         }
-    }
-}
 
-struct Disc {
-    pos: Point2,
-    radius: f32
-}
+        for collision in &collisions.0 {
+            let victim = collision.other;
+            let damage = match damage.get(collision.entity) {
+                Some(damage) => damage.0,
+                None => continue,
+            };
+            if bullet.get(collision.entity).is_some() && !impacts.0.contains(collision) {
+                continue;
+            }
+            if dead.get(victim).is_some() {
+                continue;
+            }
+            if invulnerable.get(victim).map_or(false, |i| i.0 > 0.0) {
+                continue;
+            }
 
-impl Disc {
-    fn new(pos: Point2, radius: f32) -> Self {
-        Disc { pos, radius }
-    }
+            let out_of_health = match health.get_mut(victim) {
+                Some(health) => {
+                    health.hp = (health.hp - damage).max(0.0);
+                    health.hp == 0.0
+                }
+                None => continue,
+            };
 
-    fn intersects(&self, other: &Disc) -> bool {
-        let d = self.pos.distance(&other.pos);
-        d < self.radius || d < other.radius
-    }
-}
+            // Getting hurt ourselves should feel worse than landing a hit
+            camera.add_trauma(if is_player.get(victim).is_some() { 0.5 } else { 0.1 });
+            sounds.play(Sound::Hit);
 
-fn bullets_update_position(bullets: &mut Bullets, dt: f32) {
-    for bullet in &mut bullets.bullets {
-        if bullet.alive {
-            bullet.pos += bullet.vel * dt;
-            if bullet.pos.x > 400.0 || bullet.pos.x < -400.0 {
-                bullet.alive = false;
+            if let Some(invulnerable) = invulnerable.get_mut(victim) {
+                invulnerable.0 = 1.0;
+            }
+            if let Some(hit_stun) = hit_stun.get_mut(victim) {
+                hit_stun.0 = 0.3;
+            }
+            if out_of_health {
+                dead.insert(victim, Dead);
             }
         }
     }
 }
 
-impl<'a, 'b> EventHandler for MainState<'a, 'b> {
+impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.game.assets.reload_changed(ctx);
+
         const DESIRED_FPS: u32 = 60;
         while timer::check_update_time(ctx, DESIRED_FPS) {
             let seconds = 1.0 / (DESIRED_FPS as f32);
-
-            {
-                let mut delta = self.world.write_resource::<DeltaTime>();
-                *delta = DeltaTime(seconds);
-            }
-
-            //player_handle_input(&mut self.player, &mut self.bullets, &self.hooks, &self.input, seconds, self.global_time);
-            //player_update_position(&mut self.player, seconds, self.global_time);
-            //bullets_update_position(&mut self.bullets, seconds);
-            //boss_update(&mut self.boss, &mut self.player, &mut self.bullets, seconds);
-            //handle_intersection(&mut self.boss, &mut self.bullets, seconds);
-            self.update_ui(ctx);
-            self.update_key_flags();
-            self.global_time = get_time(ctx);
-            self.dispatcher.dispatch(&self.world.res);
+            self.game.audio.update(seconds);
+            let transition = match self.scenes.top() {
+                Some(scene) => scene.update(&mut self.game, ctx, seconds),
+                None => Transition::None,
+            };
+            self.apply(ctx, transition);
         }
-        let mut input_state = self.world.write_resource::<InputState>();
-        input_state.just_pressed.clear();
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
         graphics::clear(ctx);
 
-        /*{
-            let assets = &mut self.assets;
-            let p = &self.player;
-            draw_actor(assets, ctx, p, self.screen_width, self.screen_height)?;
-            draw_boss(assets, ctx, &self.boss, self.screen_width, self.screen_height)?;
-            draw_bullets(assets, ctx, &self.bullets, self.screen_width, self.screen_height)?;
-            for hook in &self.hooks {
-                draw_hook(assets, ctx, *hook, self.screen_width, self.screen_height)?;
-            }
-        }*/
+        self.scenes.draw(&mut self.game, ctx)?;
+
+        graphics::present(ctx);
 
-        let debug_data_pos = graphics::Point2::new(10.0, 10.0);
-        graphics::draw(ctx, &self.debug_data, debug_data_pos, 0.0)?;
+        timer::yield_now();
 
-        use specs::Join;
+        Ok(())
+    }
 
-        let entities = self.world.entities();
-        let positions = self.world.read_storage::<Pos>();
-        let bullets = self.world.read_storage::<BulletStatus>();
-        let hooks = self.world.read_storage::<IsHook>();
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, _keymod: Mod, repeat: bool) {
+        if repeat {
+            return;
+        }
+        let transition = match self.scenes.top() {
+            Some(scene) => scene.key_down(&mut self.game, ctx, keycode),
+            None => Transition::None,
+        };
+        self.apply(ctx, transition);
+    }
 
-        for (ent, pos, bullet) in (&*entities, &positions, &bullets).join() {
-            if let BulletStatus::Alive = bullet {
-                draw_bullet_sprite(&mut self.assets, ctx, *pos, self.screen_width, self.screen_height)?;
-            }
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+        if let Some(scene) = self.scenes.top() {
+            scene.key_up(&mut self.game, keycode);
+        }
+    }
+
+    fn controller_button_down_event(&mut self, ctx: &mut Context, btn: Button, _instance_id: i32) {
+        let transition = match self.scenes.top() {
+            Some(scene) => scene.button_down(&mut self.game, ctx, btn),
+            None => Transition::None,
+        };
+        self.apply(ctx, transition);
+    }
+
+    fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _instance_id: i32) {
+        if let Some(scene) = self.scenes.top() {
+            scene.button_up(&mut self.game, btn);
         }
+    }
 
-        for (ent, pos, not_bullet, not_hook) in (&*entities, &positions, !&bullets, !&hooks).join() {
-            draw_debug_sprite(&mut self.assets, ctx, *pos, self.screen_width, self.screen_height)?;
+    fn controller_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: i16, _instance_id: i32) {
+        if let Some(scene) = self.scenes.top() {
+            scene.axis(&mut self.game, axis, value);
         }
+    }
 
-        for (pos, hook) in (&positions, &hooks).join() {
-            draw_bullet_sprite(&mut self.assets, ctx, *pos, self.screen_width, self.screen_height)?;
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: i32, y: i32) {
+        if let Some(scene) = self.scenes.top() {
+            scene.mouse_down(&mut self.game, button, x, y);
         }
+    }
+}
 
-        graphics::present(ctx);
+/// Reads the user's bindings if they've saved any, or the defaults if not.
+/// A broken bindings file gets reported and ignored.
+fn load_bindings(ctx: &mut Context) -> Bindings {
+    let path = if ctx.filesystem.is_file(USER_BINDINGS) {
+        USER_BINDINGS
+    } else {
+        DEFAULT_BINDINGS
+    };
+    let mut source = String::new();
+    let read = ctx.filesystem.open(path)
+        .and_then(|mut file| file.read_to_string(&mut source).map_err(GameError::from));
+    if let Err(e) = read {
+        println!("Could not read {}, using default controls: {}", path, e);
+        return Bindings::default();
+    }
+    Bindings::parse(&source).unwrap_or_else(|e| {
+        println!("{}: {}", path, e);
+        println!("Using default controls");
+        Bindings::default()
+    })
+}
 
-        timer::yield_now();
+fn save_bindings(ctx: &mut Context, bindings: &Bindings) -> GameResult<()> {
+    let mut file = ctx.filesystem.create(USER_BINDINGS)?;
+    file.write_all(bindings.to_ron().as_bytes())?;
+    Ok(())
+}
 
-        Ok(())
+fn write_quicksave(ctx: &mut Context, save: &SaveFile) -> GameResult<()> {
+    let mut file = ctx.filesystem.create(QUICKSAVE)?;
+    file.write_all(save.to_ron().as_bytes())?;
+    Ok(())
+}
+
+fn read_quicksave(ctx: &mut Context) -> GameResult<SaveFile> {
+    let mut source = String::new();
+    ctx.filesystem.open(QUICKSAVE)?.read_to_string(&mut source)?;
+    SaveFile::parse(&source).map_err(|e| {
+        GameError::ResourceLoadError(format!("{}: {}", QUICKSAVE, e))
+    })
+}
+
+/// Command line options
+struct Options {
+    /// Relative to resources/, like everything in the ggez filesystem
+    level_path: String,
+    headless: bool,
+    /// Reload assets when their files change
+    dev: bool,
+    /// Play no sound at all
+    mute: bool,
+    /// Master volume, from 0 to 1
+    volume: f32,
+    frames: u32,
+    record_path: Option<path::PathBuf>,
+    replay_path: Option<path::PathBuf>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let arg_value = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|i| args.get(i + 1))
+        };
+        let frames = match arg_value("--frames").map(|n| n.parse::<u32>()) {
+            Some(Ok(frames)) => frames,
+            Some(Err(e)) => return Err(format!("Bad --frames: {}", e)),
+            None => 60,
+        };
+        let volume = match arg_value("--volume").map(|v| v.parse::<f32>()) {
+            Some(Ok(volume)) if volume >= 0.0 && volume <= 1.0 => volume,
+            Some(Ok(volume)) => return Err(format!("Bad --volume: {} isn't between 0 and 1", volume)),
+            Some(Err(e)) => return Err(format!("Bad --volume: {}", e)),
+            None => 1.0,
+        };
+        Ok(Options {
+            level_path: arg_value("--level").cloned().unwrap_or_else(|| DEFAULT_LEVEL.to_owned()),
+            headless: args.iter().any(|arg| arg == "--headless"),
+            dev: args.iter().any(|arg| arg == "--dev"),
+            mute: args.iter().any(|arg| arg == "--mute"),
+            volume,
+            frames,
+            record_path: arg_value("--record").map(path::PathBuf::from),
+            replay_path: arg_value("--replay").map(path::PathBuf::from),
+        })
     }
+}
 
-    fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        match keycode {
-            Keycode::Left => {
-                self.register_keypress(Input::LEFT);
-            }
-            Keycode::Right => {
-                self.register_keypress(Input::RIGHT);
-            }
-            Keycode::Up | Keycode::Space => {
-                self.register_keypress(Input::JUMP);
-            }
-            Keycode::Z => {
-                self.register_keypress(Input::SHOOT);
-            }
-            Keycode::X => {
-                self.register_keypress(Input::TOOL);
-            }
-            Keycode::Escape => ctx.quit().unwrap(),
-            _ => (), // Do nothing
+fn read_recording(path: &path::Path) -> Result<Recording, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Recording::read(&mut file).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write_recording(path: &path::Path, recording: &Recording) -> Result<(), String> {
+    let mut file = fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    recording.write(&mut file).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Runs the game with no window, then prints where everything ended up.
+///
+/// Without a replay nobody is at the controls, and it runs for
+/// `options.frames` ticks. With one, it runs until the replay ends and
+/// fails if the world doesn't match the recording.
+fn run_headless(resources: &path::Path, options: &Options) -> Result<(), String> {
+    let replay = match options.replay_path {
+        Some(ref path) => Some(read_recording(path)?),
+        None => None,
+    };
+    let level_path = replay.as_ref().map_or(options.level_path.as_str(), |r| r.level.as_str());
+
+    let file = resources.join(level_path.trim_start_matches('/'));
+    let source = fs::read_to_string(&file)
+        .map_err(|e| format!("{}: {}", file.display(), e))?;
+    let level = Level::parse(&source)
+        .map_err(|e| format!("{}: {}", level_path, e))?;
+
+    let file = resources.join(WEAPONS.trim_start_matches('/'));
+    let source = fs::read_to_string(&file)
+        .map_err(|e| format!("{}: {}", file.display(), e))?;
+    let arsenal = Arsenal::parse(&source)
+        .map_err(|e| format!("{}: {}", WEAPONS, e))?;
+
+    let file = resources.join(PHYSICS.trim_start_matches('/'));
+    let source = fs::read_to_string(&file)
+        .map_err(|e| format!("{}: {}", file.display(), e))?;
+    let physics = PhysicsConfig::parse(&source)
+        .map_err(|e| format!("{}: {}", PHYSICS, e))?;
+
+    let mut sim = Simulation::new(&level, &arsenal, &physics);
+    if options.record_path.is_some() {
+        sim.start_recording(level_path);
+    }
+    let frames = match replay {
+        Some(replay) => {
+            let frames = replay.len() as u32;
+            sim.start_playback(replay);
+            frames
+        }
+        None => options.frames,
+    };
+    // Nothing to hear, but the sounds still go through the motions
+    let mut audio = Audio::null();
+    let mut sounds_played = 0;
+    for _ in 0..frames {
+        sim.step(1.0 / 60.0);
+        for sound in sim.take_sounds() {
+            audio.play(sound);
+            sounds_played += 1;
         }
+        audio.update(1.0 / 60.0);
     }
 
-    fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        match keycode {
-            Keycode::Left => {
-                self.unregister_keypress(Input::LEFT);
-            }
-            Keycode::Right => {
-                self.unregister_keypress(Input::RIGHT);
-            }
-            Keycode::Z => {
-                self.unregister_keypress(Input::SHOOT);
-            }
-            Keycode::X => {
-                self.unregister_keypress(Input::TOOL);
-            }
-            Keycode::Up | Keycode::Space => {
-                self.unregister_keypress(Input::JUMP);
-            }
-            _ => (), // Do nothing
-        }
+    if let Some(ref path) = options.record_path {
+        write_recording(path, &sim.finish_recording().unwrap())?;
+    }
+    let replay_result = sim.take_replay_result();
+
+    let entities = sim.world.entities();
+    let pos = sim.world.read_storage::<Pos>();
+    let vel = sim.world.read_storage::<Vel>();
+    let is_player = sim.world.read_storage::<IsPlayer>();
+    let is_boss = sim.world.read_storage::<IsBoss>();
+    let dead = sim.world.read_storage::<Dead>();
+    println!("After {} frames:", frames);
+    for (ent, pos, vel, _) in (&*entities, &pos, &vel, &is_player).join() {
+        println!("  player {:?} pos {:?} vel {:?} dead {}", ent, pos.0, vel.0, dead.get(ent).is_some());
+    }
+    for (ent, pos, vel, _) in (&*entities, &pos, &vel, &is_boss).join() {
+        println!("  boss {:?} pos {:?} vel {:?} dead {}", ent, pos.0, vel.0, dead.get(ent).is_some());
+    }
+    println!("  checksum {:016x}", sim.checksum());
+    println!("  sounds played: {}", sounds_played);
+    let pools = sim.world.read_resource::<Pools>();
+    println!("  player bullets: {}", pools.player_bullets);
+    println!("  enemy bullets: {}", pools.enemy_bullets);
+    println!("  particles: {}", pools.particles);
+
+    match replay_result {
+        Some(false) => Err("replay desynced: the world doesn't match the recording".to_owned()),
+        _ => Ok(()),
     }
 }
 
@@ -1063,34 +2029,57 @@ impl<'a, 'b> EventHandler for MainState<'a, 'b> {
 ///
 
 pub fn main() {
-    let mut cb = ContextBuilder::new("YEEHAW", "ggez")
-        .window_setup(conf::WindowSetup::default().title("YEEHAW"))
-        .window_mode(conf::WindowMode::default().dimensions(640, 360));
+    // Designers can point the game at a different level with
+    // `--level /levels/whatever.ron` (relative to resources/). `--record
+    // file` saves the session's inputs and `--replay file` plays them back.
+    // `--dev` reloads images and fonts when their files change. `--volume
+    // 0.5` turns everything down, and `--mute` plays no sound at all.
+    let args: Vec<String> = env::args().collect();
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(2);
+        }
+    };
 
     // We add the CARGO_MANIFEST_DIR/resources to the filesystems paths so
     // we we look in the cargo project for files.
-    if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
-        let mut path = path::PathBuf::from(manifest_dir);
-        path.push("resources");
-        println!("Adding path {:?}", path);
-        // We need this re-assignment alas, see
-        // https://aturon.github.io/ownership/builders.html
-        // under "Consuming builders"
-        cb = cb.add_resource_path(path);
-    } else {
-        unimplemented!();
+    let resources = match env::var("CARGO_MANIFEST_DIR") {
+        Ok(manifest_dir) => path::PathBuf::from(manifest_dir).join("resources"),
+        Err(_) => unimplemented!(),
+    };
+
+    // `--headless --frames N` runs the simulation without opening a window,
+    // for CI boxes with no display
+    if options.headless {
+        if let Err(e) = run_headless(&resources, &options) {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
     }
 
+    println!("Adding path {:?}", resources);
+    // We need this re-assignment alas, see
+    // https://aturon.github.io/ownership/builders.html
+    // under "Consuming builders"
+    let cb = ContextBuilder::new("YEEHAW", "ggez")
+        .window_setup(conf::WindowSetup::default().title("YEEHAW"))
+        .window_mode(conf::WindowMode::default().dimensions(640, 360))
+        .add_resource_path(resources.clone());
+
     let mut ctx = &mut cb.build().unwrap();
     set_default_filter(ctx, FilterMode::Nearest);
 
-    match MainState::new(&mut ctx) {
+    match MainState::new(&mut ctx, &resources, &options) {
         Err(e) => {
             println!("Could not load game!");
             println!("Error: {}", e);
         }
         Ok(ref mut game) => {
             let result = event::run(&mut ctx, game);
+            game.shut_down();
             if let Err(e) = result {
                 println!("Error encountered running game: {}", e);
             } else {
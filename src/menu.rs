@@ -0,0 +1,292 @@
+//! The scenes that aren't the game: title screen, pause menu, results and
+//! the controls screen.
+
+use ggez::event::{Button, Keycode};
+use ggez::graphics::{self, Point2};
+use ggez::{Context, GameResult};
+
+use bindings::Rebinder;
+use playing::Playing;
+use scene::{Scene, Transition};
+use {save_bindings, Bindings, Game, Input, ALL_INPUTS};
+
+/// What a key or button means on a menu
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MenuInput {
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+impl MenuInput {
+    /// Menus answer to the arrow keys, Enter and Escape, as well as to
+    /// whatever's bound to moving and jumping
+    fn from_key(bindings: &Bindings, keycode: Keycode) -> Option<MenuInput> {
+        match keycode {
+            Keycode::Up => Some(MenuInput::Up),
+            Keycode::Down => Some(MenuInput::Down),
+            Keycode::Return => Some(MenuInput::Select),
+            Keycode::Escape => Some(MenuInput::Back),
+            _ => bindings.key(keycode).and_then(MenuInput::from_input),
+        }
+    }
+
+    fn from_button(bindings: &Bindings, btn: Button) -> Option<MenuInput> {
+        match btn {
+            Button::Start => Some(MenuInput::Select),
+            Button::Back => Some(MenuInput::Back),
+            _ => bindings.button(btn).and_then(MenuInput::from_input),
+        }
+    }
+
+    fn from_input(input: Input) -> Option<MenuInput> {
+        match input {
+            Input::UP => Some(MenuInput::Up),
+            Input::DOWN => Some(MenuInput::Down),
+            Input::JUMP => Some(MenuInput::Select),
+            _ => None,
+        }
+    }
+}
+
+/// A title over a list of choices, one of them highlighted
+struct Menu {
+    title: &'static str,
+    items: &'static [&'static str],
+    selected: usize,
+}
+
+impl Menu {
+    fn new(title: &'static str, items: &'static [&'static str]) -> Menu {
+        Menu { title, items, selected: 0 }
+    }
+
+    /// Moves the highlight, and returns the highlighted item once it's
+    /// picked
+    fn input(&mut self, input: MenuInput) -> Option<&'static str> {
+        match input {
+            MenuInput::Up => self.selected = (self.selected + self.items.len() - 1) % self.items.len(),
+            MenuInput::Down => self.selected = (self.selected + 1) % self.items.len(),
+            MenuInput::Select => return Some(self.items[self.selected]),
+            MenuInput::Back => (),
+        }
+        None
+    }
+
+    /// Draws the menu centered on the screen, over a dark backdrop. A
+    /// translucent backdrop lets the frozen game show through.
+    fn draw(&self, ctx: &mut Context, game: &Game, backdrop_alpha: f32) -> GameResult<()> {
+        let (w, h) = (ctx.conf.window_mode.width as f32, ctx.conf.window_mode.height as f32);
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, backdrop_alpha))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, graphics::Rect::new(0.0, 0.0, w, h))?;
+        graphics::set_color(ctx, graphics::WHITE)?;
+
+        let font = game.assets.font("ui")?;
+        let top = h / 2.0 - 24.0 * (self.items.len() as f32 + 2.0) / 2.0;
+        let title = graphics::Text::new(ctx, self.title, font)?;
+        graphics::draw(ctx, &title, Point2::new((w - title.width() as f32) / 2.0, top), 0.0)?;
+        for (i, item) in self.items.iter().enumerate() {
+            let line = if i == self.selected { format!("> {} <", item) } else { item.to_string() };
+            let text = graphics::Text::new(ctx, &line, font)?;
+            let y = top + 24.0 * (i as f32 + 2.0);
+            graphics::draw(ctx, &text, Point2::new((w - text.width() as f32) / 2.0, y), 0.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the game opens on
+pub struct Title {
+    menu: Menu,
+}
+
+impl Title {
+    pub fn new() -> Title {
+        Title { menu: Menu::new("YEEHAW", &["Start", "Controls", "Quit"]) }
+    }
+
+    fn input(&mut self, game: &mut Game, ctx: &mut Context, input: Option<MenuInput>) -> Transition {
+        if input == Some(MenuInput::Back) {
+            return Transition::Quit;
+        }
+        match input.and_then(|input| self.menu.input(input)) {
+            Some("Start") => Playing::start(ctx, game),
+            Some("Controls") => Transition::Push(Box::new(Controls::new(&game.bindings))),
+            Some("Quit") => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+}
+
+impl Scene for Title {
+    fn draw(&mut self, game: &mut Game, ctx: &mut Context) -> GameResult<()> {
+        self.menu.draw(ctx, game, 1.0)
+    }
+
+    fn key_down(&mut self, game: &mut Game, ctx: &mut Context, keycode: Keycode) -> Transition {
+        let input = MenuInput::from_key(&game.bindings, keycode);
+        self.input(game, ctx, input)
+    }
+
+    fn button_down(&mut self, game: &mut Game, ctx: &mut Context, btn: Button) -> Transition {
+        let input = MenuInput::from_button(&game.bindings, btn);
+        self.input(game, ctx, input)
+    }
+}
+
+/// The pause menu, over the frozen game
+pub struct Paused {
+    menu: Menu,
+}
+
+impl Paused {
+    pub fn new() -> Paused {
+        Paused { menu: Menu::new("PAUSED", &["Resume", "Controls", "Quit to title", "Quit game"]) }
+    }
+
+    fn input(&mut self, game: &mut Game, input: Option<MenuInput>) -> Transition {
+        if input == Some(MenuInput::Back) {
+            return Transition::Pop;
+        }
+        match input.and_then(|input| self.menu.input(input)) {
+            Some("Resume") => Transition::Pop,
+            Some("Controls") => Transition::Push(Box::new(Controls::new(&game.bindings))),
+            Some("Quit to title") => Transition::Reset(Box::new(Title::new())),
+            Some("Quit game") => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+}
+
+impl Scene for Paused {
+    fn draw(&mut self, game: &mut Game, ctx: &mut Context) -> GameResult<()> {
+        self.menu.draw(ctx, game, 0.6)
+    }
+
+    fn key_down(&mut self, game: &mut Game, _ctx: &mut Context, keycode: Keycode) -> Transition {
+        let input = MenuInput::from_key(&game.bindings, keycode);
+        self.input(game, input)
+    }
+
+    fn button_down(&mut self, game: &mut Game, _ctx: &mut Context, btn: Button) -> Transition {
+        let input = MenuInput::from_button(&game.bindings, btn);
+        self.input(game, input)
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// Game over or victory, shown over the game once the fight is decided
+pub struct Results {
+    menu: Menu,
+}
+
+impl Results {
+    pub fn game_over() -> Results {
+        Results { menu: Menu::new("GAME OVER", &["Try again", "Quit to title", "Quit game"]) }
+    }
+
+    pub fn victory() -> Results {
+        Results { menu: Menu::new("VICTORY", &["Play again", "Quit to title", "Quit game"]) }
+    }
+
+    fn input(&mut self, game: &mut Game, ctx: &mut Context, input: Option<MenuInput>) -> Transition {
+        match input.and_then(|input| self.menu.input(input)) {
+            Some("Try again") | Some("Play again") => Playing::start(ctx, game),
+            Some("Quit to title") => Transition::Reset(Box::new(Title::new())),
+            Some("Quit game") => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+}
+
+impl Scene for Results {
+    fn draw(&mut self, game: &mut Game, ctx: &mut Context) -> GameResult<()> {
+        self.menu.draw(ctx, game, 0.6)
+    }
+
+    fn key_down(&mut self, game: &mut Game, ctx: &mut Context, keycode: Keycode) -> Transition {
+        let input = MenuInput::from_key(&game.bindings, keycode);
+        self.input(game, ctx, input)
+    }
+
+    fn button_down(&mut self, game: &mut Game, ctx: &mut Context, btn: Button) -> Transition {
+        let input = MenuInput::from_button(&game.bindings, btn);
+        self.input(game, ctx, input)
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// The rebinding screen. Walks through every Input asking for a key or
+/// button, then saves the new bindings.
+pub struct Controls {
+    rebinder: Rebinder,
+}
+
+impl Controls {
+    pub fn new(bindings: &Bindings) -> Controls {
+        Controls { rebinder: Rebinder::new(bindings) }
+    }
+
+    /// Once every Input has had its turn, uses and saves the new bindings
+    fn finish_if_done(&mut self, game: &mut Game, ctx: &mut Context) -> Transition {
+        if self.rebinder.current().is_some() {
+            return Transition::None;
+        }
+        game.bindings = self.rebinder.bindings().clone();
+        if let Err(e) = save_bindings(ctx, &game.bindings) {
+            println!("Could not save bindings: {}", e);
+        }
+        Transition::Pop
+    }
+}
+
+impl Scene for Controls {
+    fn draw(&mut self, game: &mut Game, ctx: &mut Context) -> GameResult<()> {
+        let mut lines = vec![
+            "CONTROLS".to_owned(),
+            "Press a key or button for each action.".to_owned(),
+            "Enter keeps the current binding, Escape cancels.".to_owned(),
+            String::new(),
+        ];
+        for &input in &ALL_INPUTS {
+            let marker = if self.rebinder.current() == Some(input) { ">" } else { " " };
+            lines.push(format!("{} {:?}: {}", marker, input, self.rebinder.bindings().describe(input)));
+        }
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.8))?;
+        let (w, h) = (ctx.conf.window_mode.width as f32, ctx.conf.window_mode.height as f32);
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, graphics::Rect::new(0.0, 0.0, w, h))?;
+        graphics::set_color(ctx, graphics::WHITE)?;
+
+        for (i, line) in lines.iter().enumerate() {
+            let text = graphics::Text::new(ctx, line, game.assets.font("ui")?)?;
+            graphics::draw(ctx, &text, Point2::new(40.0, 40.0 + 24.0 * i as f32), 0.0)?;
+        }
+        Ok(())
+    }
+
+    fn key_down(&mut self, game: &mut Game, ctx: &mut Context, keycode: Keycode) -> Transition {
+        match keycode {
+            Keycode::Escape => return Transition::Pop, // Cancelled
+            Keycode::Return => self.rebinder.skip(),
+            _ => self.rebinder.bind_key(keycode),
+        }
+        self.finish_if_done(game, ctx)
+    }
+
+    fn button_down(&mut self, game: &mut Game, ctx: &mut Context, btn: Button) -> Transition {
+        self.rebinder.bind_button(btn);
+        self.finish_if_done(game, ctx)
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,187 @@
+//! Particle effects: muzzle flashes, sparks, dust.
+//!
+//! Particles are ordinary entities drawn from a Pool, with a Sprite for
+//! drawing and a Vel that RigidBodyPhysics moves like anything else. Effects
+//! are described by EmitterDefs. Systems ask for one-off bursts by pushing
+//! them onto the Effects resource; an Emitter component gives off particles
+//! continuously for as long as it's active.
+//!
+//! Particles count toward the world checksum, so their randomness comes
+//! from a seeded generator and replays stay in sync.
+
+use ggez::graphics::{Color, Point2};
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+
+/// Everything that makes one kind of effect look the way it does
+#[derive(Debug)]
+pub struct EmitterDef {
+    /// Particles per burst
+    pub count: u32,
+    /// Particles per second from a continuous Emitter
+    pub rate: f32,
+    /// Seconds each particle lasts, picked between these
+    pub lifetime: (f32, f32),
+    /// Starting speed, picked between these
+    pub speed: (f32, f32),
+    /// Total angle, in radians, the particles fan out over around the
+    /// direction they're emitted in
+    pub spread: f32,
+    pub gravity: bool,
+    /// Color and scale go smoothly from start to end over a particle's life
+    pub start_color: Color,
+    pub end_color: Color,
+    pub start_scale: f32,
+    pub end_scale: f32,
+}
+
+pub static MUZZLE_FLASH: EmitterDef = EmitterDef {
+    count: 6,
+    rate: 0.0,
+    lifetime: (0.05, 0.12),
+    speed: (80.0, 200.0),
+    spread: 0.6,
+    gravity: false,
+    start_color: Color { r: 1.0, g: 0.95, b: 0.6, a: 1.0 },
+    end_color: Color { r: 1.0, g: 0.4, b: 0.1, a: 0.0 },
+    start_scale: 1.5,
+    end_scale: 0.5,
+};
+
+pub static IMPACT: EmitterDef = EmitterDef {
+    count: 8,
+    rate: 0.0,
+    lifetime: (0.1, 0.3),
+    speed: (60.0, 180.0),
+    spread: 6.28,
+    gravity: true,
+    start_color: Color { r: 1.0, g: 0.8, b: 0.4, a: 1.0 },
+    end_color: Color { r: 0.6, g: 0.2, b: 0.1, a: 0.0 },
+    start_scale: 1.0,
+    end_scale: 0.5,
+};
+
+pub static LANDING_DUST: EmitterDef = EmitterDef {
+    count: 10,
+    rate: 0.0,
+    lifetime: (0.2, 0.45),
+    speed: (30.0, 90.0),
+    spread: 2.8,
+    gravity: false,
+    start_color: Color { r: 0.8, g: 0.75, b: 0.7, a: 0.8 },
+    end_color: Color { r: 0.8, g: 0.75, b: 0.7, a: 0.0 },
+    start_scale: 1.0,
+    end_scale: 2.5,
+};
+
+pub static HOOK_SPARKS: EmitterDef = EmitterDef {
+    count: 12,
+    rate: 0.0,
+    lifetime: (0.15, 0.35),
+    speed: (50.0, 150.0),
+    spread: 6.28,
+    gravity: true,
+    start_color: Color { r: 0.9, g: 0.9, b: 1.0, a: 1.0 },
+    end_color: Color { r: 0.4, g: 0.5, b: 1.0, a: 0.0 },
+    start_scale: 1.0,
+    end_scale: 0.25,
+};
+
+pub static RUN_DUST: EmitterDef = EmitterDef {
+    count: 1,
+    rate: 12.0,
+    lifetime: (0.2, 0.35),
+    speed: (10.0, 30.0),
+    spread: 1.2,
+    gravity: false,
+    start_color: Color { r: 0.8, g: 0.75, b: 0.7, a: 0.6 },
+    end_color: Color { r: 0.8, g: 0.75, b: 0.7, a: 0.0 },
+    start_scale: 0.75,
+    end_scale: 1.5,
+};
+
+/// A particle's progress through its life. Pooled particles that aren't in
+/// use have `def` set to None.
+#[derive(Component, Debug, Default)]
+pub struct Particle {
+    pub def: Option<&'static EmitterDef>,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+impl Particle {
+    /// How far through its life the particle is, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        (self.age / self.lifetime).min(1.0)
+    }
+}
+
+/// Gives off particles continuously while `active`
+#[derive(Component, Debug)]
+pub struct Emitter {
+    pub def: &'static EmitterDef,
+    pub active: bool,
+    /// Direction to emit in, in radians from the positive x axis
+    pub direction: f32,
+    /// Fractional particles owed from previous ticks
+    pub owed: f32,
+}
+
+impl Emitter {
+    pub fn new(def: &'static EmitterDef, direction: f32) -> Emitter {
+        Emitter {
+            def,
+            active: false,
+            direction,
+            owed: 0.0,
+        }
+    }
+}
+
+/// A one-off effect waiting to be spawned
+#[derive(Debug)]
+pub struct Burst {
+    pub def: &'static EmitterDef,
+    pub pos: Point2,
+    pub direction: f32,
+}
+
+/// Bursts requested this tick. ParticleSystem spawns and clears them.
+#[derive(Debug, Default)]
+pub struct Effects(pub Vec<Burst>);
+
+impl Effects {
+    pub fn burst(&mut self, def: &'static EmitterDef, pos: Point2, direction: f32) {
+        self.0.push(Burst { def, pos, direction });
+    }
+}
+
+/// The particles' random numbers. Always starts from the same seed, so a
+/// replay sees the same sparks fly as the recording did.
+pub struct ParticleRng(pub XorShiftRng);
+
+impl Default for ParticleRng {
+    fn default() -> Self {
+        ParticleRng(XorShiftRng::from_seed(*b"yeehaw particles"))
+    }
+}
+
+impl ParticleRng {
+    /// A random number between `range.0` and `range.1`
+    pub fn between(&mut self, range: (f32, f32)) -> f32 {
+        if range.1 > range.0 {
+            self.0.gen_range(range.0, range.1)
+        } else {
+            range.0
+        }
+    }
+}
+
+pub fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    Color {
+        r: a.r + (b.r - a.r) * t,
+        g: a.g + (b.g - a.g) * t,
+        b: a.b + (b.b - a.b) * t,
+        a: a.a + (b.a - a.a) * t,
+    }
+}
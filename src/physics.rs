@@ -0,0 +1,255 @@
+//! How the player moves: gravity, jumping, running and swinging.
+//!
+//! The numbers live in a RON file so they can be tuned without touching
+//! code, and the debug overlay can nudge them while the game runs:
+//!
+//! ```text
+//! PhysicsConfig(
+//!     gravity: 500.0,
+//!     jump_velocity: 300.0,
+//!     jump_cut_velocity: 120.0,
+//!     coyote_time: 0.1,
+//!     jump_buffer: 0.1,
+//!     walk_speed: 100.0,
+//!     ground_accel: 1000.0,
+//!     air_accel: 500.0,
+//!     ground_friction: 800.0,
+//!     hook_reach: 100.0,
+//!     swing_constant: 62.8,
+//!     reel_speed: 80.0,
+//!     min_rope: 24.0,
+//!     max_rope: 150.0,
+//! )
+//! ```
+//!
+//! Anything left out keeps its default. Bullet speeds belong to each gun, in
+//! weapons.ron.
+//!
+//! Replays and saves only play out the same under the physics they were
+//! made with.
+
+use ron;
+
+/// Everything's in world units and seconds
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// Downwards acceleration of anything with HasGravity
+    pub gravity: f32,
+    /// Upwards speed at the start of a jump, or added when jumping off a rope
+    pub jump_velocity: f32,
+    /// Letting go of jump while still going up faster than this slows the
+    /// player down to it, so tapping jump gives a short hop
+    pub jump_cut_velocity: f32,
+    /// How long after walking off a ledge the player can still jump
+    pub coyote_time: f32,
+    /// How long before landing a jump press still counts
+    pub jump_buffer: f32,
+    /// Top speed the player can run at. Swings and knockback can push
+    /// them faster, and running doesn't slow them back down in the air.
+    pub walk_speed: f32,
+    /// How quickly running speeds the player up or turns them around
+    pub ground_accel: f32,
+    pub air_accel: f32,
+    /// How quickly the player slows down on the ground when not running,
+    /// or when going faster than walk_speed
+    pub ground_friction: f32,
+    /// How close a hook has to be for the player to latch onto it
+    pub hook_reach: f32,
+    /// A swing's angular frequency, in radians per second, is this divided
+    /// by the rope length, so shorter ropes swing faster
+    pub swing_constant: f32,
+    /// How fast the rope reels in and out
+    pub reel_speed: f32,
+    pub min_rope: f32,
+    pub max_rope: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            gravity: 500.0,
+            jump_velocity: 300.0,
+            jump_cut_velocity: 120.0,
+            coyote_time: 0.1,
+            jump_buffer: 0.1,
+            walk_speed: 100.0,
+            ground_accel: 1000.0,
+            air_accel: 500.0,
+            ground_friction: 800.0,
+            hook_reach: 100.0,
+            swing_constant: 10.0 * 6.28,
+            reel_speed: 80.0,
+            min_rope: 24.0,
+            max_rope: 150.0,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    pub fn parse(source: &str) -> Result<PhysicsConfig, String> {
+        let config: PhysicsConfig = ron::de::from_str(source).map_err(|e| {
+            format!("line {}, column {}: {}", e.position.line, e.position.col, e.code)
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .expect("physics should always serialize")
+    }
+
+    /// Checks the settings make sense together. The jump windows can be 0,
+    /// which only lets a jump through on the exact tick the player lands or
+    /// presses jump; everything else has to be positive.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in self.params() {
+            let optional = name == "coyote_time" || name == "jump_buffer";
+            if optional && !(value >= 0.0) {
+                return Err(format!("{} can't be less than 0", name));
+            }
+            if !optional && !(value > 0.0) {
+                return Err(format!("{} has to be more than 0", name));
+            }
+        }
+        if self.jump_cut_velocity > self.jump_velocity {
+            return Err("jump_cut_velocity is faster than jump_velocity".to_owned());
+        }
+        if self.min_rope > self.max_rope {
+            return Err("min_rope is longer than max_rope".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Every setting by name, in the order the file lists them
+    pub fn params(&self) -> Vec<(&'static str, f32)> {
+        vec![
+            ("gravity", self.gravity),
+            ("jump_velocity", self.jump_velocity),
+            ("jump_cut_velocity", self.jump_cut_velocity),
+            ("coyote_time", self.coyote_time),
+            ("jump_buffer", self.jump_buffer),
+            ("walk_speed", self.walk_speed),
+            ("ground_accel", self.ground_accel),
+            ("air_accel", self.air_accel),
+            ("ground_friction", self.ground_friction),
+            ("hook_reach", self.hook_reach),
+            ("swing_constant", self.swing_constant),
+            ("reel_speed", self.reel_speed),
+            ("min_rope", self.min_rope),
+            ("max_rope", self.max_rope),
+        ]
+    }
+
+    /// The `index`th setting in `params`, for changing it
+    pub fn param_mut(&mut self, index: usize) -> Option<&mut f32> {
+        match index {
+            0 => Some(&mut self.gravity),
+            1 => Some(&mut self.jump_velocity),
+            2 => Some(&mut self.jump_cut_velocity),
+            3 => Some(&mut self.coyote_time),
+            4 => Some(&mut self.jump_buffer),
+            5 => Some(&mut self.walk_speed),
+            6 => Some(&mut self.ground_accel),
+            7 => Some(&mut self.air_accel),
+            8 => Some(&mut self.ground_friction),
+            9 => Some(&mut self.hook_reach),
+            10 => Some(&mut self.swing_constant),
+            11 => Some(&mut self.reel_speed),
+            12 => Some(&mut self.min_rope),
+            13 => Some(&mut self.max_rope),
+            _ => None,
+        }
+    }
+
+    /// Horizontal speed after running for `dt` seconds in direction `run`
+    /// (from -1 to 1) from speed `vx`. Only ground friction takes away
+    /// speed beyond what running gives, so swings and knockback keep their
+    /// momentum until the player lands.
+    pub fn run_velocity(&self, vx: f32, run: f32, grounded: bool, dt: f32) -> f32 {
+        let target = run * self.walk_speed;
+        let speeding_up = run != 0.0 && (vx * run <= 0.0 || vx.abs() < target.abs());
+        if speeding_up {
+            let accel = if grounded { self.ground_accel } else { self.air_accel };
+            approach(vx, target, accel * dt)
+        } else if grounded {
+            approach(vx, target, self.ground_friction * dt)
+        } else {
+            vx
+        }
+    }
+
+    /// Angular frequency of a pendulum on a rope of length `dist`, in
+    /// radians per second
+    pub fn swing_frequency(&self, dist: f32) -> f64 {
+        f64::from(self.swing_constant) / f64::from(dist)
+    }
+}
+
+/// Moves `value` towards `target` by at most `step`
+fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    #[test]
+    fn running_speeds_up_to_walk_speed() {
+        let physics = PhysicsConfig::default();
+        let mut vx = physics.run_velocity(0.0, 1.0, true, DT);
+        assert!(vx > 0.0 && vx < physics.walk_speed);
+        for _ in 0..60 {
+            vx = physics.run_velocity(vx, 1.0, true, DT);
+        }
+        assert_eq!(vx, physics.walk_speed);
+    }
+
+    #[test]
+    fn turning_around_goes_through_zero() {
+        let physics = PhysicsConfig::default();
+        let vx = physics.run_velocity(physics.walk_speed, -1.0, true, DT);
+        assert_eq!(vx, physics.walk_speed - physics.ground_accel * DT);
+    }
+
+    #[test]
+    fn air_control_is_weaker() {
+        let physics = PhysicsConfig::default();
+        let ground = physics.run_velocity(0.0, 1.0, true, DT);
+        let air = physics.run_velocity(0.0, 1.0, false, DT);
+        assert!(air < ground);
+    }
+
+    #[test]
+    fn momentum_lasts_until_landing() {
+        let physics = PhysicsConfig::default();
+        let fast = physics.walk_speed * 3.0;
+        // In the air, neither running along nor letting go slows us down
+        assert_eq!(physics.run_velocity(fast, 1.0, false, DT), fast);
+        assert_eq!(physics.run_velocity(fast, 0.0, false, DT), fast);
+        // On the ground, friction brings us back down to walk speed
+        let mut vx = fast;
+        for _ in 0..60 {
+            vx = physics.run_velocity(vx, 1.0, true, DT);
+        }
+        assert_eq!(vx, physics.walk_speed);
+    }
+
+    #[test]
+    fn stops_on_the_ground_without_input() {
+        let physics = PhysicsConfig::default();
+        let mut vx = -physics.walk_speed;
+        for _ in 0..60 {
+            vx = physics.run_velocity(vx, 0.0, true, DT);
+        }
+        assert_eq!(vx, 0.0);
+    }
+}
@@ -0,0 +1,334 @@
+//! The game itself, as a scene: a Simulation, plus everything needed to
+//! draw it and feed it input.
+
+use super::*;
+use debug::DebugOverlay;
+use menu::{Controls, Paused, Results};
+use scene::{Scene, Transition};
+
+/// Seconds to keep the game running after the fight is decided, before
+/// putting up the result
+const ENDING_DELAY: f32 = 2.0;
+
+pub struct Playing {
+    /// The weapon readout, and the string it was made from, so it's only
+    /// rebuilt when something changes
+    hud: graphics::Text,
+    hud_str: String,
+    sim: Simulation<'static, 'static>,
+    /// The level this game was started on, for naming it in saves
+    level_path: String,
+    renderer: SpriteRenderer,
+    debug: DebugOverlay,
+    /// Seconds since the fight was decided, and whether the player won
+    ending: Option<(f32, bool)>,
+}
+
+impl Playing {
+    /// Starts a fresh game of `game.level_path`, or of the replay if there's
+    /// one waiting to be played
+    pub fn new(ctx: &mut Context, game: &mut Game) -> GameResult<Playing> {
+        let hud = graphics::Text::new(ctx, " ", game.assets.font("ui")?)?;
+
+        let screen_width = ctx.conf.window_mode.width;
+        let screen_height = ctx.conf.window_mode.height;
+
+        // A replay brings its own level along. It only gets played once;
+        // starting over afterwards is a normal game.
+        let replay = match game.replay_path.take() {
+            Some(path) => Some(read_recording(&path).map_err(GameError::ResourceLoadError)?),
+            None => None,
+        };
+        let level_path = replay.as_ref().map_or(game.level_path.as_str(), |r| r.level.as_str()).to_owned();
+
+        let level = read_level(ctx, &level_path)?;
+        let arsenal = read_arsenal(ctx)?;
+        let physics = read_physics(ctx)?;
+        let mut sim = Simulation::new(&level, &arsenal, &physics);
+        if game.record_path.is_some() {
+            sim.start_recording(&level_path);
+        }
+        if let Some(replay) = replay {
+            sim.start_playback(replay);
+        }
+        sim.world.write_resource::<Camera>().set_viewport(screen_width, screen_height);
+
+        Ok(Playing {
+            hud,
+            hud_str: String::new(),
+            sim,
+            level_path,
+            renderer: SpriteRenderer::default(),
+            debug: DebugOverlay::default(),
+            ending: None,
+        })
+    }
+
+    /// A transition into a fresh game, or nowhere if it couldn't be loaded
+    pub fn start(ctx: &mut Context, game: &mut Game) -> Transition {
+        match Playing::new(ctx, game) {
+            Ok(playing) => Transition::Reset(Box::new(playing)),
+            Err(e) => {
+                println!("Could not start the game: {}", e);
+                Transition::None
+            }
+        }
+    }
+
+    fn update_ui(&mut self, ctx: &mut Context, game: &Game, dt: f32) -> GameResult<()> {
+        let hud_str = {
+            let is_player = self.sim.world.read_storage::<IsPlayer>();
+            let weapons = self.sim.world.read_storage::<Weapons>();
+            match (&weapons, &is_player).join().next() {
+                Some((weapons, _)) => {
+                    let weapon = weapons.current();
+                    if weapon.is_reloading() {
+                        format!("{} (reloading)", weapon.def.name)
+                    } else {
+                        format!("{} {}/{}", weapon.def.name, weapon.ammo, weapon.def.magazine)
+                    }
+                }
+                None => " ".to_owned(),
+            }
+        };
+        if hud_str != self.hud_str {
+            self.hud = graphics::Text::new(ctx, &hud_str, game.assets.font("ui")?)?;
+            self.hud_str = hud_str;
+        }
+
+        self.debug.update(ctx, &self.sim.world, game.assets.font("ui")?, dt)
+    }
+
+    /// Plays what the last tick asked for, and picks music to suit the fight
+    fn update_audio(&mut self, game: &mut Game) {
+        for sound in self.sim.take_sounds() {
+            game.audio.play(sound);
+        }
+        if self.ending.is_some() {
+            return;
+        }
+
+        let boss_fight = {
+            let health = self.sim.world.read_storage::<Health>();
+            let is_boss = self.sim.world.read_storage::<IsBoss>();
+            let dead = self.sim.world.read_storage::<Dead>();
+            (&health, &is_boss, !&dead).join().any(|(health, _, _)| health.hp < health.max)
+        };
+        game.audio.play_music(if boss_fight { "boss" } else { "level" }, 2.0);
+    }
+
+    /// Whether the fight is over: Some(false) once the player's dead, and
+    /// Some(true) once everything else that can die has
+    fn outcome(&self) -> Option<bool> {
+        let entities = self.sim.world.entities();
+        let health = self.sim.world.read_storage::<Health>();
+        let is_player = self.sim.world.read_storage::<IsPlayer>();
+        let dead = self.sim.world.read_storage::<Dead>();
+
+        if (&is_player, &dead).join().next().is_some() {
+            return Some(false);
+        }
+        let mut enemies = (&*entities, &health, !&is_player).join().peekable();
+        if enemies.peek().is_some() && enemies.all(|(ent, _, _)| dead.get(ent).is_some()) {
+            return Some(true);
+        }
+        None
+    }
+
+    /// Writes out the input recording, if there is one
+    fn save_recording(&mut self, game: &Game) -> Result<(), String> {
+        match (game.record_path.as_ref(), self.sim.finish_recording()) {
+            (Some(path), Some(recording)) => {
+                write_recording(path, &recording)?;
+                println!("Saved {} ticks of input to {:?}", recording.len(), path);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn quick_save(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let save = self.sim.save(&self.level_path);
+        write_quicksave(ctx, &save)?;
+        println!("Saved the game to {}", QUICKSAVE);
+        Ok(())
+    }
+
+    /// Replaces the world with the quick-saved one. Whatever's been recorded
+    /// so far is written out first, since the recording can't carry on
+    /// across a load. Physics tweaks from the debug overlay carry over.
+    fn quick_load(&mut self, ctx: &mut Context, game: &Game) -> GameResult<()> {
+        let save = read_quicksave(ctx)?;
+        let level = read_level(ctx, &save.level)?;
+        let arsenal = read_arsenal(ctx)?;
+        let physics = self.sim.world.read_resource::<PhysicsConfig>().clone();
+
+        if let Err(e) = self.save_recording(game) {
+            println!("Could not save recording: {}", e);
+        }
+        let screen_width = ctx.conf.window_mode.width;
+        let screen_height = ctx.conf.window_mode.height;
+        self.sim = Simulation::load(&save, &level, &arsenal, &physics);
+        self.sim.world.write_resource::<Camera>().set_viewport(screen_width, screen_height);
+        self.level_path = save.level;
+        self.ending = None;
+        println!("Loaded the game from {}", QUICKSAVE);
+        Ok(())
+    }
+
+    /// Lets go of everything before something else takes over the input
+    fn pause(&mut self, scene: Box<dyn Scene>) -> Transition {
+        self.sim.release_all();
+        Transition::Push(scene)
+    }
+}
+
+impl Scene for Playing {
+    fn update(&mut self, game: &mut Game, ctx: &mut Context, dt: f32) -> Transition {
+        self.sim.step(dt);
+        if let Err(e) = self.update_ui(ctx, game, dt) {
+            println!("Could not update the HUD: {}", e);
+        }
+        self.update_audio(game);
+
+        match self.sim.take_replay_result() {
+            Some(true) => println!("Replay finished, no desync"),
+            Some(false) => println!("Replay finished, DESYNC: the world doesn't match the recording"),
+            None => (),
+        }
+
+        if self.ending.is_none() {
+            self.ending = self.outcome().map(|won| (0.0, won));
+        }
+        match self.ending {
+            Some((elapsed, won)) if elapsed + dt >= ENDING_DELAY => {
+                self.ending = None;
+                game.audio.stop_music(1.0);
+                if won {
+                    self.pause(Box::new(Results::victory()))
+                } else {
+                    self.pause(Box::new(Results::game_over()))
+                }
+            }
+            Some((elapsed, won)) => {
+                self.ending = Some((elapsed + dt, won));
+                Transition::None
+            }
+            None => Transition::None,
+        }
+    }
+
+    fn draw(&mut self, game: &mut Game, ctx: &mut Context) -> GameResult<()> {
+        self.debug.record_frame(timer::duration_to_f64(timer::get_delta(ctx)) as f32);
+
+        {
+            let camera = self.sim.world.read_resource::<Camera>();
+
+            draw_tilemap(ctx, &self.sim.world.read_resource::<Tilemap>(), &camera)?;
+
+            let hud_pos = graphics::Point2::new(10.0, 10.0);
+            graphics::draw(ctx, &self.hud, hud_pos, 0.0)?;
+
+            draw_ropes(ctx, &self.sim.world, &camera)?;
+            self.renderer.draw(ctx, &self.sim.world, &game.assets, &camera)?;
+        }
+
+        self.debug.draw(ctx, &self.sim.world)
+    }
+
+    fn key_down(&mut self, game: &mut Game, ctx: &mut Context, keycode: Keycode) -> Transition {
+        match keycode {
+            Keycode::Escape => self.pause(Box::new(Paused::new())),
+            Keycode::F1 => self.pause(Box::new(Controls::new(&game.bindings))),
+            Keycode::F3 => {
+                self.debug.toggle();
+                Transition::None
+            }
+            Keycode::F5 => {
+                if let Err(e) = self.quick_save(ctx) {
+                    println!("Could not save the game: {}", e);
+                }
+                Transition::None
+            }
+            Keycode::F9 => {
+                if let Err(e) = self.quick_load(ctx, game) {
+                    println!("Could not load the game: {}", e);
+                }
+                Transition::None
+            }
+            _ => {
+                if self.debug.visible() && self.debug.key_down(&self.sim.world, keycode) {
+                    return Transition::None;
+                }
+                if let Some(input) = game.bindings.key(keycode) {
+                    self.sim.press(input);
+                }
+                Transition::None
+            }
+        }
+    }
+
+    fn key_up(&mut self, game: &mut Game, keycode: Keycode) {
+        if let Some(input) = game.bindings.key(keycode) {
+            self.sim.release(input);
+        }
+    }
+
+    fn button_down(&mut self, game: &mut Game, _ctx: &mut Context, btn: Button) -> Transition {
+        if btn == Button::Start {
+            return self.pause(Box::new(Paused::new()));
+        }
+        if let Some(input) = game.bindings.button(btn) {
+            self.sim.press(input);
+        }
+        Transition::None
+    }
+
+    fn button_up(&mut self, game: &mut Game, btn: Button) {
+        if let Some(input) = game.bindings.button(btn) {
+            self.sim.release(input);
+        }
+    }
+
+    fn mouse_down(&mut self, _game: &mut Game, button: MouseButton, x: i32, y: i32) {
+        if button == MouseButton::Left && self.debug.visible() {
+            self.debug.pick(&self.sim.world, x, y);
+        }
+    }
+
+    fn axis(&mut self, game: &mut Game, axis: Axis, value: i16) {
+        if let Some((x, y)) = game.bindings.stick(axis, value) {
+            self.sim.set_stick(x, y);
+        }
+    }
+
+    fn leave(&mut self, game: &mut Game) {
+        if let Err(e) = self.save_recording(game) {
+            println!("Could not save recording: {}", e);
+        }
+    }
+}
+
+fn read_level(ctx: &mut Context, path: &str) -> GameResult<Level> {
+    let mut source = String::new();
+    ctx.filesystem.open(path)?.read_to_string(&mut source)?;
+    Level::parse(&source).map_err(|e| {
+        GameError::ResourceLoadError(format!("{}: {}", path, e))
+    })
+}
+
+pub fn read_arsenal(ctx: &mut Context) -> GameResult<Arsenal> {
+    let mut source = String::new();
+    ctx.filesystem.open(WEAPONS)?.read_to_string(&mut source)?;
+    Arsenal::parse(&source).map_err(|e| {
+        GameError::ResourceLoadError(format!("{}: {}", WEAPONS, e))
+    })
+}
+
+fn read_physics(ctx: &mut Context) -> GameResult<PhysicsConfig> {
+    let mut source = String::new();
+    ctx.filesystem.open(PHYSICS)?.read_to_string(&mut source)?;
+    PhysicsConfig::parse(&source).map_err(|e| {
+        GameError::ResourceLoadError(format!("{}: {}", PHYSICS, e))
+    })
+}
@@ -0,0 +1,188 @@
+//! Object pools.
+//!
+//! Short-lived things like bullets are created up front and recycled rather
+//! than created and deleted as they come and go. A Pool only keeps track of
+//! which items are free; making new items is left to its owner, who checks
+//! `take_growth` once in a while and adds as many as the pool asks for.
+
+use std::fmt;
+
+/// What a pool does when something asks for an item and none are free
+#[derive(Clone, Copy, Debug)]
+pub enum Growth {
+    /// Never grow. Requests fail until something is released.
+    Fixed,
+    /// Ask to double in size, up to `max` items
+    Double { max: usize },
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolStats {
+    pub in_use: usize,
+    /// The most items that have been in use at once
+    pub peak_in_use: usize,
+    pub acquired: u64,
+    pub released: u64,
+    /// Requests that found the pool empty
+    pub exhausted: u64,
+    /// Times the pool has grown
+    pub grown: u32,
+}
+
+#[derive(Debug)]
+pub struct Pool<T> {
+    free: Vec<T>,
+    capacity: usize,
+    growth: Growth,
+    /// Items the pool wants added, since it last ran dry
+    wanted: usize,
+    stats: PoolStats,
+}
+
+impl<T> Pool<T> {
+    pub fn new(growth: Growth) -> Pool<T> {
+        Pool {
+            free: vec![],
+            capacity: 0,
+            growth,
+            wanted: 0,
+            stats: PoolStats::default(),
+        }
+    }
+
+    /// Gives the pool a brand new item
+    pub fn add(&mut self, item: T) {
+        self.free.push(item);
+        self.capacity += 1;
+    }
+
+    /// Gives the pool a brand new item that's already in use, as if it had
+    /// been added and then acquired. For putting a pool back together the
+    /// way it was.
+    ///
+    /// The pool only ever holds on to free items, so there's nothing to keep
+    /// here: whoever is using the item hands it to `release` when they're
+    /// done, like any other acquired item, and it joins the free list then.
+    pub fn add_in_use(&mut self, _item: T) {
+        self.capacity += 1;
+        self.stats.in_use += 1;
+        self.stats.peak_in_use = self.stats.peak_in_use.max(self.stats.in_use);
+    }
+
+    /// Takes a free item, or returns None if they're all in use
+    pub fn acquire(&mut self) -> Option<T> {
+        match self.free.pop() {
+            Some(item) => {
+                self.stats.acquired += 1;
+                self.stats.in_use += 1;
+                self.stats.peak_in_use = self.stats.peak_in_use.max(self.stats.in_use);
+                Some(item)
+            }
+            None => {
+                self.stats.exhausted += 1;
+                if let Growth::Double { max } = self.growth {
+                    self.wanted = self.capacity.max(1).min(max.saturating_sub(self.capacity));
+                }
+                None
+            }
+        }
+    }
+
+    /// Hands back an item from `acquire` for reuse
+    pub fn release(&mut self, item: T) {
+        debug_assert!(self.stats.in_use > 0, "released more items than were acquired");
+        self.free.push(item);
+        self.stats.released += 1;
+        self.stats.in_use -= 1;
+    }
+
+    /// How many new items the pool wants `add`ed, if it has run dry since
+    /// the last call
+    pub fn take_growth(&mut self) -> usize {
+        if self.wanted > 0 {
+            self.stats.grown += 1;
+        }
+        ::std::mem::replace(&mut self.wanted, 0)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> &PoolStats {
+        &self.stats
+    }
+}
+
+impl<T> fmt::Display for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} in use (peak {}), ran out {} times, grew {} times",
+            self.stats.in_use, self.capacity, self.stats.peak_in_use, self.stats.exhausted, self.stats.grown
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(growth: Growth, items: u32) -> Pool<u32> {
+        let mut pool = Pool::new(growth);
+        for i in 0..items {
+            pool.add(i);
+        }
+        pool
+    }
+
+    #[test]
+    fn recycles_released_items() {
+        let mut pool = pool(Growth::Fixed, 2);
+        let a = pool.acquire().unwrap();
+        let b = pool.acquire().unwrap();
+        assert_ne!(a, b);
+        assert_eq!(pool.acquire(), None);
+
+        pool.release(a);
+        assert_eq!(pool.acquire(), Some(a));
+        assert_eq!(pool.stats().in_use, 2);
+        assert_eq!(pool.stats().peak_in_use, 2);
+        assert_eq!(pool.stats().exhausted, 1);
+    }
+
+    #[test]
+    fn fixed_pools_never_grow() {
+        let mut pool = pool(Growth::Fixed, 1);
+        pool.acquire();
+        assert_eq!(pool.acquire(), None);
+        assert_eq!(pool.take_growth(), 0);
+        assert_eq!(pool.stats().grown, 0);
+    }
+
+    #[test]
+    fn doubling_pools_grow_up_to_their_max() {
+        let mut pool = pool(Growth::Double { max: 6 }, 4);
+        for _ in 0..4 {
+            pool.acquire();
+        }
+        assert_eq!(pool.acquire(), None);
+        // Doubling would make 8, but the max is 6
+        assert_eq!(pool.take_growth(), 2);
+        assert_eq!(pool.take_growth(), 0);
+        pool.add(4);
+        pool.add(5);
+        assert_eq!(pool.capacity(), 6);
+        assert_eq!(pool.stats().grown, 1);
+    }
+
+    #[test]
+    fn items_added_in_use_can_be_released() {
+        let mut pool = pool(Growth::Fixed, 0);
+        pool.add_in_use(7);
+        assert_eq!(pool.acquire(), None);
+        pool.release(7);
+        assert_eq!(pool.acquire(), Some(7));
+        assert_eq!(pool.capacity(), 1);
+    }
+}
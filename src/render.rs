@@ -0,0 +1,177 @@
+//! Drawing entities.
+//!
+//! Anything with a Pos and a Sprite gets drawn, back to front by layer.
+//! Sprites sharing an image within a layer go out as one SpriteBatch.
+
+use ggez::graphics::{self, Color, DrawParam, Point2, Vector2};
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::{Context, GameResult};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+use animation::Animation;
+use camera::Camera;
+use assets::Assets;
+use physics::PhysicsConfig;
+use {quantize, BulletStatus, Dead, Facing, IsSwingTarget, Pos, SwingData_};
+
+/// Draw order. Higher layers are drawn on top.
+pub mod z {
+    pub const HOOK: i32 = 0;
+    pub const PICKUP: i32 = 5;
+    pub const ENEMY: i32 = 10;
+    pub const PLAYER: i32 = 20;
+    pub const BULLET: i32 = 30;
+    pub const PARTICLE: i32 = 40;
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Sprite {
+    /// Name of the image in the asset manifest. Components outlive windows
+    /// (the simulation runs headless too), so they hold names rather than
+    /// images.
+    pub image: String,
+    /// The point of the image that sits on Pos, from (0, 0) at the top-left
+    /// to (1, 1) at the bottom-right
+    pub offset: Point2,
+    pub scale: Vector2,
+    pub layer: i32,
+    /// Tints the image. White leaves it as it is.
+    pub color: Color,
+    pub visible: bool,
+}
+
+impl Sprite {
+    /// A sprite centered on its entity, at its natural size
+    pub fn new(image: &str, layer: i32) -> Sprite {
+        Sprite {
+            image: image.to_owned(),
+            offset: Point2::new(0.5, 0.5),
+            scale: Vector2::new(1.0, 1.0),
+            layer,
+            color: graphics::WHITE,
+            visible: true,
+        }
+    }
+}
+
+/// Segments each rope is drawn with
+const ROPE_SEGMENTS: usize = 12;
+
+/// How far a completely slack rope sags, as a fraction of its length
+const MAX_SAG: f32 = 0.2;
+
+/// Draws the rope between each swinging entity and its hook.
+///
+/// A rope bearing the swinger's weight is drawn straight. Near the top of a
+/// big swing, where it's barely holding anything up, it sags.
+pub fn draw_ropes(ctx: &mut Context, world: &World, camera: &Camera) -> GameResult<()> {
+    let positions = world.read_storage::<Pos>();
+    let swing_data = world.read_storage::<SwingData_>();
+    let targets = world.read_storage::<IsSwingTarget>();
+    let gravity = world.read_resource::<PhysicsConfig>().gravity;
+
+    let hook = match (&positions, &targets).join().map(|(pos, _)| pos.0).next() {
+        Some(hook) => hook,
+        None => return Ok(()),
+    };
+    for (pos, sd) in (&positions, &swing_data).join() {
+        // Tension per unit mass, relative to gravity: the part of the weight
+        // along the rope plus what it takes to keep the swinger moving in a
+        // circle.
+        let tension = sd.theta.cos() + sd.dist * sd.omega * sd.omega / gravity;
+        let sag = sd.dist * MAX_SAG * (1.0 - tension).max(0.0).min(1.0);
+
+        let points: Vec<Point2> = (0..ROPE_SEGMENTS + 1)
+            .map(|i| {
+                let s = i as f32 / ROPE_SEGMENTS as f32;
+                let along = hook + (pos.0 - hook) * s;
+                // A parabola is close enough to a hanging rope
+                let drop = 4.0 * sag * s * (1.0 - s);
+                camera.world_to_screen(Point2::new(along.x, along.y - drop))
+            })
+            .collect();
+        graphics::line(ctx, &points, camera.zoom.max(1.0))?;
+    }
+    Ok(())
+}
+
+/// Holds on to a SpriteBatch per image between frames, so their buffers get
+/// reused.
+#[derive(Default)]
+pub struct SpriteRenderer {
+    batches: HashMap<String, SpriteBatch>,
+    /// The Assets generation the batches' images came from
+    generation: u32,
+}
+
+impl SpriteRenderer {
+    pub fn draw(&mut self, ctx: &mut Context, world: &World, assets: &Assets, camera: &Camera) -> GameResult<()> {
+        let positions = world.read_storage::<Pos>();
+        let sprites = world.read_storage::<Sprite>();
+        let animations = world.read_storage::<Animation>();
+        let facings = world.read_storage::<Facing>();
+        let bullets = world.read_storage::<BulletStatus>();
+        let dead = world.read_storage::<Dead>();
+
+        // Hot reloading replaced some images; batches made with the old ones
+        // have to go
+        if assets.generation() != self.generation {
+            self.batches.clear();
+            self.generation = assets.generation();
+        }
+
+        let mut queue: Vec<(i32, &str, DrawParam)> = vec![];
+        for (pos, sprite, animation, facing, bullet, _) in (&positions, &sprites, animations.maybe(), facings.maybe(), bullets.maybe(), !&dead).join() {
+            if !sprite.visible {
+                continue;
+            }
+            if let Some(&BulletStatus::Dead) = bullet {
+                continue;
+            }
+            // Images face right. Flipping the x scale mirrors around the
+            // offset.
+            let flip = facing.map_or(1.0, |f| f.to_f32());
+            let src = match animation {
+                Some(animation) => animation.sheet.src_rect(animation.frame()),
+                None => graphics::Rect::one(),
+            };
+            let param = DrawParam {
+                src,
+                dest: quantize(camera.world_to_screen(pos.0)),
+                offset: sprite.offset,
+                scale: Point2::new(sprite.scale.x * flip * camera.zoom, sprite.scale.y * camera.zoom),
+                color: Some(sprite.color),
+                ..Default::default()
+            };
+            queue.push((sprite.layer, &sprite.image, param));
+        }
+        // Stable, so things on the same layer keep a consistent order
+        queue.sort_by_key(|&(layer, _, _)| layer);
+
+        let mut start = 0;
+        while start < queue.len() {
+            let layer = queue[start].0;
+            let end = start + queue[start..].iter().take_while(|&&(l, _, _)| l == layer).count();
+
+            let mut used = vec![];
+            for &(_, image, param) in &queue[start..end] {
+                if !self.batches.contains_key(image) {
+                    self.batches.insert(image.to_owned(), SpriteBatch::new(assets.image(image)?.clone()));
+                }
+                self.batches.get_mut(image).unwrap().add(param);
+                if !used.contains(&image) {
+                    used.push(image);
+                }
+            }
+            for image in used {
+                let batch = self.batches.get_mut(image).unwrap();
+                graphics::draw_ex(ctx, batch, DrawParam::default())?;
+                batch.clear();
+            }
+
+            start = end;
+        }
+        Ok(())
+    }
+}
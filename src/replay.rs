@@ -0,0 +1,338 @@
+//! Input recordings.
+//!
+//! The simulation is deterministic given the inputs it sees each tick, so a
+//! recording of those inputs is enough to reproduce a whole play session. A
+//! recording file is:
+//!
+//! ```text
+//! "YHRP"              magic
+//! u8                  format version
+//! u16 + bytes         level path, UTF-8
+//! u32                 number of ticks
+//! (u32, u16)*         runs of (frame, repeat count)
+//! u8 + u64            1 and a checksum of the final world state, or just 0
+//! ```
+//!
+//! All integers are little-endian. A frame packs the held inputs into the
+//! lowest byte, the just-pressed inputs into the next, and the analog stick's
+//! x and y, as signed multiples of 1/127, into the two above that.
+//!
+//! Version 1 recordings predate analog input and store u16 frames with just
+//! the two input bytes. They can still be read.
+
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+
+use {Input, InputState, ALL_INPUTS};
+
+const MAGIC: &[u8; 4] = b"YHRP";
+const VERSION: u8 = 2;
+
+fn input_bit(input: Input) -> u8 {
+    match input {
+        Input::LEFT => 1 << 0,
+        Input::RIGHT => 1 << 1,
+        Input::JUMP => 1 << 2,
+        Input::SHOOT => 1 << 3,
+        Input::TOOL => 1 << 4,
+        Input::UP => 1 << 5,
+        Input::DOWN => 1 << 6,
+        Input::SWITCH => 1 << 7,
+    }
+}
+
+fn to_bits(inputs: &HashSet<Input>) -> u8 {
+    inputs.iter().fold(0, |bits, &input| bits | input_bit(input))
+}
+
+fn from_bits(bits: u8) -> HashSet<Input> {
+    ALL_INPUTS.iter().cloned().filter(|&input| bits & input_bit(input) != 0).collect()
+}
+
+/// What the simulation saw of the controls during one tick
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Frame(u32);
+
+impl Frame {
+    pub fn capture(input: &InputState) -> Frame {
+        // The stick is already quantized to 1/127 steps, so this is exact
+        let stick_x = (input.stick_x * 127.0).round() as i8 as u8;
+        let stick_y = (input.stick_y * 127.0).round() as i8 as u8;
+        Frame(
+            u32::from(to_bits(&input.keys))
+                | u32::from(to_bits(&input.just_pressed)) << 8
+                | u32::from(stick_x) << 16
+                | u32::from(stick_y) << 24,
+        )
+    }
+
+    pub fn apply(self, input: &mut InputState) {
+        input.keys = from_bits(self.0 as u8);
+        input.just_pressed = from_bits((self.0 >> 8) as u8);
+        input.stick_x = f32::from((self.0 >> 16) as u8 as i8) / 127.0;
+        input.stick_y = f32::from((self.0 >> 24) as u8 as i8) / 127.0;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Recording {
+    /// The level the recording was made on
+    pub level: String,
+    frames: Vec<Frame>,
+    /// Checksum of the world after the last frame, if we got that far
+    pub checksum: Option<u64>,
+}
+
+impl Recording {
+    pub fn new(level: &str) -> Recording {
+        Recording {
+            level: level.to_owned(),
+            frames: vec![],
+            checksum: None,
+        }
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn frame(&self, tick: usize) -> Option<Frame> {
+        self.frames.get(tick).cloned()
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+
+        let level = self.level.as_bytes();
+        if level.len() > u16::max_value() as usize {
+            return Err(invalid("level path too long"));
+        }
+        write_u16(w, level.len() as u16)?;
+        w.write_all(level)?;
+
+        write_u32(w, self.frames.len() as u32)?;
+        let mut i = 0;
+        while i < self.frames.len() {
+            let frame = self.frames[i];
+            let run = self.frames[i..].iter()
+                .take(u16::max_value() as usize)
+                .take_while(|&&f| f == frame)
+                .count();
+            write_u32(w, frame.0)?;
+            write_u16(w, run as u16)?;
+            i += run;
+        }
+
+        match self.checksum {
+            Some(checksum) => {
+                w.write_all(&[1])?;
+                write_u64(w, checksum)
+            }
+            None => w.write_all(&[0]),
+        }
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Recording> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a replay file"));
+        }
+        let version = read_u8(r)?;
+        if version != 1 && version != VERSION {
+            return Err(invalid(&format!("unsupported replay version {}", version)));
+        }
+
+        let mut level = vec![0; read_u16(r)? as usize];
+        r.read_exact(&mut level)?;
+        let level = String::from_utf8(level).map_err(|_| invalid("level path isn't UTF-8"))?;
+
+        let len = read_u32(r)? as usize;
+        let mut frames = Vec::with_capacity(len);
+        while frames.len() < len {
+            let frame = match version {
+                1 => Frame(u32::from(read_u16(r)?)),
+                _ => Frame(read_u32(r)?),
+            };
+            let run = read_u16(r)? as usize;
+            if run == 0 || frames.len() + run > len {
+                return Err(invalid("corrupt frame data"));
+            }
+            frames.extend(::std::iter::repeat(frame).take(run));
+        }
+
+        let checksum = match read_u8(r)? {
+            0 => None,
+            _ => Some(read_u64(r)?),
+        };
+
+        Ok(Recording { level, frames, checksum })
+    }
+}
+
+/// A recording being fed back into the simulation
+pub struct Playback {
+    recording: Recording,
+    tick: usize,
+}
+
+impl Playback {
+    pub fn new(recording: Recording) -> Playback {
+        Playback { recording, tick: 0 }
+    }
+
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        let frame = self.recording.frame(self.tick);
+        if frame.is_some() {
+            self.tick += 1;
+        }
+        frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.recording.len()
+    }
+
+    pub fn expected_checksum(&self) -> Option<u64> {
+        self.recording.checksum
+    }
+}
+
+/// FNV-1a, which is plenty for spotting desyncs
+pub struct Checksum(u64);
+
+impl Checksum {
+    pub fn new() -> Checksum {
+        Checksum(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn add_u32(&mut self, n: u32) {
+        for i in 0..4 {
+            self.0 ^= u64::from((n >> (i * 8)) as u8);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn add_f32(&mut self, x: f32) {
+        self.add_u32(x.to_bits());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from(buf[0]) | u16::from(buf[1]) << 8)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let lo = u32::from(read_u16(r)?);
+    let hi = u32::from(read_u16(r)?);
+    Ok(lo | hi << 16)
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let lo = u64::from(read_u32(r)?);
+    let hi = u64::from(read_u32(r)?);
+    Ok(lo | hi << 32)
+}
+
+fn write_u16<W: Write>(w: &mut W, n: u16) -> io::Result<()> {
+    w.write_all(&[n as u8, (n >> 8) as u8])
+}
+
+fn write_u32<W: Write>(w: &mut W, n: u32) -> io::Result<()> {
+    write_u16(w, n as u16)?;
+    write_u16(w, (n >> 16) as u16)
+}
+
+fn write_u64<W: Write>(w: &mut W, n: u64) -> io::Result<()> {
+    write_u32(w, n as u32)?;
+    write_u32(w, (n >> 32) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let mut input = InputState::default();
+        input.register_keypress(Input::LEFT);
+        input.register_keypress(Input::JUMP);
+        input.set_stick(Some(-0.5), Some(1.0));
+
+        let mut replayed = InputState::default();
+        Frame::capture(&input).apply(&mut replayed);
+        assert_eq!(replayed.keys, input.keys);
+        assert_eq!(replayed.just_pressed, input.just_pressed);
+        assert_eq!(replayed.stick_x, input.stick_x);
+        assert_eq!(replayed.stick_y, input.stick_y);
+    }
+
+    #[test]
+    fn recordings_round_trip() {
+        let mut input = InputState::default();
+        let mut recording = Recording::new("/levels/arena.ron");
+        for tick in 0..200 {
+            if tick == 50 {
+                input.register_keypress(Input::RIGHT);
+            }
+            if tick == 120 {
+                input.unregister_keypress(Input::RIGHT);
+                input.register_keypress(Input::SHOOT);
+            }
+            recording.push(Frame::capture(&input));
+            input.just_pressed.clear();
+        }
+        recording.checksum = Some(0x0123_4567_89ab_cdef);
+
+        let mut bytes = vec![];
+        recording.write(&mut bytes).unwrap();
+        let read = Recording::read(&mut &bytes[..]).unwrap();
+        assert_eq!(read.level, recording.level);
+        assert_eq!(read.len(), recording.len());
+        for tick in 0..recording.len() {
+            assert_eq!(read.frame(tick), recording.frame(tick));
+        }
+        assert_eq!(read.checksum, recording.checksum);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let bytes = b"RIFF\x02\x00\x00";
+        assert!(Recording::read(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn checksums_tell_states_apart() {
+        let hash = |values: &[f32]| {
+            let mut checksum = Checksum::new();
+            for &x in values {
+                checksum.add_f32(x);
+            }
+            checksum.finish()
+        };
+        assert_eq!(hash(&[1.0, 2.0]), hash(&[1.0, 2.0]));
+        assert_ne!(hash(&[1.0, 2.0]), hash(&[2.0, 1.0]));
+        assert_ne!(hash(&[0.0]), hash(&[-0.0]));
+    }
+}
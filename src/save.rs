@@ -0,0 +1,434 @@
+//! Saved games.
+//!
+//! A save is a RON file listing every entity in play: what kind of thing it
+//! is, and the parts of its state that change as the game goes on. Loading
+//! respawns each entity the way the level would and then puts that state
+//! back. Anything that never changes, like sprites and collision layers,
+//! comes from the spawn functions rather than the file, and particles are
+//! left out altogether.
+//!
+//! Every file records the version of the format it was written in. Adding a
+//! field is safe without a new version, since missing fields get their
+//! defaults. Anything else (renaming, removing or reshaping a field) should
+//! bump VERSION, keep the old structs in a module named after the old
+//! version, and teach `upgrade` to convert them, so older saves still load.
+
+use ron;
+
+use super::*;
+use weapon::Weapon;
+
+/// The version of the save format written by this build
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SaveFile {
+    pub version: u32,
+    /// The level the game was started on, for its tilemap
+    pub level: String,
+    /// GlobalTime, which swings are timed against
+    pub time: f64,
+    pub camera: (f32, f32),
+    pub entities: Vec<(Kind, SavedEntity)>,
+    /// Bullets waiting in the pools, which have nothing else worth saving
+    pub spare_player_bullets: usize,
+    pub spare_enemy_bullets: usize,
+}
+
+/// Which spawn function recreates an entity
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Kind {
+    Player,
+    Hook,
+    Walker,
+    Boss,
+    Pickup(String),
+    PlayerBullet,
+    EnemyBullet,
+}
+
+/// Everything about an entity that can change during play. Components the
+/// entity doesn't have are left out.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SavedEntity {
+    pub pos: Option<(f32, f32)>,
+    pub vel: Option<(f32, f32)>,
+    pub facing: Option<Facing>,
+    pub aim: Option<(f32, f32)>,
+    pub is_jumping: Option<bool>,
+    pub jump_windows: Option<JumpWindows>,
+    pub grounded: Option<bool>,
+    pub shoot_cooldown: Option<f32>,
+    pub swing: Option<SwingData_>,
+    pub swing_target: bool,
+    pub bullet: Option<SavedBullet>,
+    pub health: Option<f32>,
+    pub hit_stun: Option<f32>,
+    pub invulnerable: Option<f32>,
+    pub dead: bool,
+    pub boss_phase: Option<BossPhase>,
+    pub phase_timer: Option<f32>,
+    pub slamming: bool,
+    pub weapons: Option<SavedWeapons>,
+}
+
+/// A bullet in flight, set up for whichever gun fired it
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedBullet {
+    pub damage: f32,
+    pub radius: f32,
+    pub sprite: String,
+    pub scale: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedWeapons {
+    pub carried: Vec<SavedWeapon>,
+    pub current: usize,
+}
+
+/// A carried gun, by name in the weapons file
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedWeapon {
+    pub name: String,
+    pub ammo: u32,
+    pub reload: f32,
+}
+
+/// Just enough of any version's save to tell which version it is
+#[derive(Deserialize)]
+#[serde(rename = "SaveFile")]
+struct Header {
+    version: u32,
+}
+
+impl SaveFile {
+    pub fn parse(source: &str) -> Result<SaveFile, String> {
+        let format_error = |e: ron::error::SpannedError| {
+            format!("line {}, column {}: {}", e.position.line, e.position.col, e.code)
+        };
+        let header: Header = ron::de::from_str(source).map_err(format_error)?;
+        upgrade(header.version, source).map_err(|e| match e {
+            UpgradeError::Parse(e) => format_error(e),
+            UpgradeError::Version(e) => e,
+        })
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .expect("saves should always serialize")
+    }
+
+    /// Records everything in `world` worth saving
+    pub fn capture(world: &World, level: &str) -> SaveFile {
+        let entities = world.entities();
+        let pos = world.read_storage::<Pos>();
+        let vel = world.read_storage::<Vel>();
+        let facing = world.read_storage::<Facing>();
+        let aim = world.read_storage::<Aim>();
+        let is_jumping = world.read_storage::<IsJumping>();
+        let jump_windows = world.read_storage::<JumpWindows>();
+        let grounded = world.read_storage::<Grounded>();
+        let shoot_cooldown = world.read_storage::<ShootCooldown>();
+        let swing_data = world.read_storage::<SwingData_>();
+        let is_target = world.read_storage::<IsSwingTarget>();
+        let bullet = world.read_storage::<BulletStatus>();
+        let damage = world.read_storage::<Damage>();
+        let collider = world.read_storage::<Collider>();
+        let sprite = world.read_storage::<Sprite>();
+        let health = world.read_storage::<Health>();
+        let hit_stun = world.read_storage::<HitStun>();
+        let invulnerable = world.read_storage::<Invulnerable>();
+        let dead = world.read_storage::<Dead>();
+        let boss_phase = world.read_storage::<BossPhase>();
+        let phase_timer = world.read_storage::<PhaseTimer>();
+        let slamming = world.read_storage::<Slamming>();
+        let weapons = world.read_storage::<Weapons>();
+
+        let is_player = world.read_storage::<IsPlayer>();
+        let is_hook = world.read_storage::<IsHook>();
+        let patrol = world.read_storage::<Patrol>();
+        let is_boss = world.read_storage::<IsBoss>();
+        let pickup = world.read_storage::<WeaponPickup>();
+        let is_enemy_bullet = world.read_storage::<IsEnemyBullet>();
+        let kind_of = |ent: Entity| {
+            if is_player.get(ent).is_some() {
+                Some(Kind::Player)
+            } else if is_hook.get(ent).is_some() {
+                Some(Kind::Hook)
+            } else if patrol.get(ent).is_some() {
+                Some(Kind::Walker)
+            } else if is_boss.get(ent).is_some() {
+                Some(Kind::Boss)
+            } else if let Some(pickup) = pickup.get(ent) {
+                Some(Kind::Pickup(pickup.0.clone()))
+            } else if bullet.get(ent).is_some() && is_enemy_bullet.get(ent).is_some() {
+                Some(Kind::EnemyBullet)
+            } else if bullet.get(ent).is_some() {
+                Some(Kind::PlayerBullet)
+            } else {
+                None
+            }
+        };
+
+        let mut saved = vec![];
+        let mut spare_player_bullets = 0;
+        let mut spare_enemy_bullets = 0;
+        for ent in entities.join() {
+            let kind = match kind_of(ent) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let live_bullet = match bullet.get(ent) {
+                Some(BulletStatus::Alive) => true,
+                Some(BulletStatus::Dead) => {
+                    match kind {
+                        Kind::EnemyBullet => spare_enemy_bullets += 1,
+                        _ => spare_player_bullets += 1,
+                    }
+                    continue;
+                }
+                None => false,
+            };
+            let state = SavedEntity {
+                pos: pos.get(ent).map(|p| (p.0.x, p.0.y)),
+                vel: vel.get(ent).map(|v| (v.0.x, v.0.y)),
+                facing: facing.get(ent).cloned(),
+                aim: aim.get(ent).map(|a| (a.0.x, a.0.y)),
+                is_jumping: is_jumping.get(ent).map(|j| j.0),
+                jump_windows: jump_windows.get(ent).cloned(),
+                grounded: grounded.get(ent).map(|g| g.0),
+                shoot_cooldown: shoot_cooldown.get(ent).map(|c| c.0),
+                swing: swing_data.get(ent).cloned(),
+                swing_target: is_target.get(ent).is_some(),
+                bullet: if live_bullet {
+                    Some(SavedBullet {
+                        damage: damage.get(ent).map_or(0.0, |d| d.0),
+                        radius: collider.get(ent).map_or(0.0, |c| c.radius),
+                        sprite: sprite.get(ent).map_or_else(String::new, |s| s.image.clone()),
+                        scale: sprite.get(ent).map_or(1.0, |s| s.scale.x),
+                    })
+                } else {
+                    None
+                },
+                health: health.get(ent).map(|h| h.hp),
+                hit_stun: hit_stun.get(ent).map(|h| h.0),
+                invulnerable: invulnerable.get(ent).map(|i| i.0),
+                dead: dead.get(ent).is_some(),
+                boss_phase: boss_phase.get(ent).cloned(),
+                phase_timer: phase_timer.get(ent).map(|t| t.0),
+                slamming: slamming.get(ent).is_some(),
+                weapons: weapons.get(ent).map(|weapons| SavedWeapons {
+                    carried: weapons.carried.iter()
+                        .map(|w| SavedWeapon { name: w.def.name.clone(), ammo: w.ammo, reload: w.reload })
+                        .collect(),
+                    current: weapons.current,
+                }),
+            };
+            saved.push((kind, state));
+        }
+
+        let camera = world.read_resource::<Camera>();
+        SaveFile {
+            version: VERSION,
+            level: level.to_owned(),
+            time: world.read_resource::<GlobalTime>().0,
+            camera: (camera.pos.x, camera.pos.y),
+            entities: saved,
+            spare_player_bullets,
+            spare_enemy_bullets,
+        }
+    }
+
+    /// Respawns the saved entities into `world`, which should already have
+    /// the level's tilemap but nothing else from it. Returns the pools,
+    /// holding the saved bullets.
+    pub fn restore(&self, world: &mut World) -> Pools {
+        let mut pools = Pools::new();
+        for &(ref kind, ref state) in &self.entities {
+            let pos = state.pos.map_or(Point2::origin(), |(x, y)| Point2::new(x, y));
+            let ent = match *kind {
+                Kind::Player => spawn_player(world, pos),
+                Kind::Hook => spawn_hook(world, pos),
+                Kind::Walker => spawn_walker(world, pos),
+                Kind::Boss => spawn_boss(world, pos),
+                Kind::Pickup(ref weapon) => match spawn_pickup(world, weapon, pos) {
+                    Some(ent) => ent,
+                    None => continue,
+                },
+                Kind::PlayerBullet | Kind::EnemyBullet => {
+                    let enemy = match *kind {
+                        Kind::EnemyBullet => true,
+                        _ => false,
+                    };
+                    let ent = if enemy { spawn_enemy_bullet(world) } else { spawn_player_bullet(world) };
+                    let pool = if enemy { &mut pools.enemy_bullets } else { &mut pools.player_bullets };
+                    if state.bullet.is_some() {
+                        pool.add_in_use(ent);
+                    } else {
+                        pool.add(ent);
+                    }
+                    ent
+                }
+            };
+            state.apply(world, ent);
+        }
+
+        for _ in 0..self.spare_player_bullets {
+            pools.player_bullets.add(spawn_player_bullet(world));
+        }
+        for _ in 0..self.spare_enemy_bullets {
+            pools.enemy_bullets.add(spawn_enemy_bullet(world));
+        }
+
+        world.write_resource::<GlobalTime>().0 = self.time;
+        world.write_resource::<Camera>().snap_to(Point2::new(self.camera.0, self.camera.1));
+        pools
+    }
+}
+
+impl SavedEntity {
+    /// Puts the saved state back on a freshly spawned `ent`
+    fn apply(&self, world: &mut World, ent: Entity) {
+        if let Some((x, y)) = self.pos {
+            world.write_storage::<Pos>().insert(ent, Pos(Point2::new(x, y)));
+        }
+        if let Some((x, y)) = self.vel {
+            world.write_storage::<Vel>().insert(ent, Vel(Vector2::new(x, y)));
+        }
+        if let Some(facing) = self.facing {
+            world.write_storage::<Facing>().insert(ent, facing);
+        }
+        if let Some((x, y)) = self.aim {
+            world.write_storage::<Aim>().insert(ent, Aim(Vector2::new(x, y)));
+        }
+        if let Some(is_jumping) = self.is_jumping {
+            world.write_storage::<IsJumping>().insert(ent, IsJumping(is_jumping));
+        }
+        if let Some(windows) = self.jump_windows {
+            world.write_storage::<JumpWindows>().insert(ent, windows);
+        }
+        if let Some(grounded) = self.grounded {
+            world.write_storage::<Grounded>().insert(ent, Grounded(grounded));
+        }
+        if let Some(cooldown) = self.shoot_cooldown {
+            world.write_storage::<ShootCooldown>().insert(ent, ShootCooldown(cooldown));
+        }
+        if let Some(swing) = self.swing {
+            world.write_storage::<SwingData_>().insert(ent, swing);
+        }
+        if self.swing_target {
+            world.write_storage::<IsSwingTarget>().insert(ent, IsSwingTarget);
+        }
+        if let Some(ref bullet) = self.bullet {
+            world.write_storage::<BulletStatus>().insert(ent, BulletStatus::Alive);
+            world.write_storage::<Damage>().insert(ent, Damage(bullet.damage));
+            if let Some(collider) = world.write_storage::<Collider>().get_mut(ent) {
+                collider.radius = bullet.radius;
+            }
+            if let Some(sprite) = world.write_storage::<Sprite>().get_mut(ent) {
+                sprite.image = bullet.sprite.clone();
+                sprite.scale = Vector2::new(bullet.scale, bullet.scale);
+            }
+        }
+        if let Some(hp) = self.health {
+            if let Some(health) = world.write_storage::<Health>().get_mut(ent) {
+                health.hp = hp;
+            }
+        }
+        if let Some(hit_stun) = self.hit_stun {
+            world.write_storage::<HitStun>().insert(ent, HitStun(hit_stun));
+        }
+        if let Some(invulnerable) = self.invulnerable {
+            world.write_storage::<Invulnerable>().insert(ent, Invulnerable(invulnerable));
+        }
+        if self.dead {
+            world.write_storage::<Dead>().insert(ent, Dead);
+        }
+        if let Some(phase) = self.boss_phase {
+            world.write_storage::<BossPhase>().insert(ent, phase);
+        }
+        if let Some(timer) = self.phase_timer {
+            world.write_storage::<PhaseTimer>().insert(ent, PhaseTimer(timer));
+        }
+        if self.slamming {
+            world.write_storage::<Slamming>().insert(ent, Slamming);
+        }
+        if let Some(ref saved) = self.weapons {
+            let arsenal = world.read_resource::<Arsenal>();
+            let mut weapons = Weapons { carried: vec![], current: 0 };
+            for saved_weapon in &saved.carried {
+                match arsenal.get(&saved_weapon.name) {
+                    Some(def) => {
+                        let mut weapon = Weapon::new(def);
+                        weapon.ammo = saved_weapon.ammo.min(def.magazine);
+                        weapon.reload = saved_weapon.reload;
+                        weapons.carried.push(weapon);
+                    }
+                    None => println!("Dropping {:?} from the save: it's not in the weapons file", saved_weapon.name),
+                }
+            }
+            if weapons.carried.is_empty() {
+                weapons = Weapons::new(arsenal.starting_weapon());
+            }
+            weapons.current = saved.current.min(weapons.carried.len() - 1);
+            world.write_storage::<Weapons>().insert(ent, weapons);
+        }
+    }
+}
+
+enum UpgradeError {
+    Parse(ron::error::SpannedError),
+    Version(String),
+}
+
+/// Reads a save written in format `version`, converting it to the current
+/// one. When the format changes, the previous version's structs get parsed
+/// here and converted step by step.
+fn upgrade(version: u32, source: &str) -> Result<SaveFile, UpgradeError> {
+    match version {
+        VERSION => ron::de::from_str(source).map_err(UpgradeError::Parse),
+        v if v > VERSION => Err(UpgradeError::Version(format!(
+            "this save is from a newer version of the game (format {}, we read up to {})", v, VERSION
+        ))),
+        v => Err(UpgradeError::Version(format!("unknown save format {}", v))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::tests::{arena_data, TICK};
+    use simulation::Simulation;
+
+    #[test]
+    fn round_trip() {
+        let (level, arsenal, physics) = arena_data();
+        let mut sim = Simulation::new(&level, &arsenal, &physics);
+        sim.press(Input::RIGHT);
+        sim.press(Input::SHOOT);
+        for _ in 0..30 {
+            sim.step(TICK);
+        }
+        sim.release_all();
+
+        let source = sim.save(DEFAULT_LEVEL).to_ron();
+        let save = SaveFile::parse(&source).unwrap();
+        let mut loaded = Simulation::load(&save, &level, &arsenal, &physics);
+        assert_eq!(loaded.save(DEFAULT_LEVEL).to_ron(), source);
+
+        // And from there both worlds carry on the same way
+        for _ in 0..60 {
+            sim.step(TICK);
+            loaded.step(TICK);
+        }
+        assert_eq!(loaded.save(DEFAULT_LEVEL).to_ron(), sim.save(DEFAULT_LEVEL).to_ron());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let source = format!("SaveFile(version: {})", VERSION + 1);
+        assert!(SaveFile::parse(&source).unwrap_err().contains("newer version"));
+    }
+}
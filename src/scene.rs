@@ -0,0 +1,107 @@
+//! Scenes: the title screen, the game itself, the pause menu and so on.
+//!
+//! Scenes sit on a stack. Only the top one gets updated and hears about
+//! input, so pushing a pause menu over the game freezes it. Drawing starts
+//! from the topmost scene that covers the whole screen, so the frozen game
+//! still shows under a menu that's only an overlay.
+
+use ggez::event::{Axis, Button, Keycode, MouseButton};
+use ggez::{Context, GameResult};
+
+use Game;
+
+/// What a scene wants done to the stack after handling something
+pub enum Transition {
+    None,
+    /// Put a scene on top of this one
+    Push(Box<dyn Scene>),
+    /// Remove this scene, uncovering the one below
+    Pop,
+    /// Throw away every scene and start over with this one
+    Reset(Box<dyn Scene>),
+    Quit,
+}
+
+pub trait Scene {
+    /// Advances the scene by one tick of `dt` seconds
+    fn update(&mut self, _game: &mut Game, _ctx: &mut Context, _dt: f32) -> Transition {
+        Transition::None
+    }
+
+    fn draw(&mut self, game: &mut Game, ctx: &mut Context) -> GameResult<()>;
+
+    fn key_down(&mut self, _game: &mut Game, _ctx: &mut Context, _keycode: Keycode) -> Transition {
+        Transition::None
+    }
+
+    fn key_up(&mut self, _game: &mut Game, _keycode: Keycode) {}
+
+    fn button_down(&mut self, _game: &mut Game, _ctx: &mut Context, _btn: Button) -> Transition {
+        Transition::None
+    }
+
+    fn button_up(&mut self, _game: &mut Game, _btn: Button) {}
+
+    fn axis(&mut self, _game: &mut Game, _axis: Axis, _value: i16) {}
+
+    /// A click at `(x, y)` in screen pixels
+    fn mouse_down(&mut self, _game: &mut Game, _button: MouseButton, _x: i32, _y: i32) {}
+
+    /// Whether the scene below should be drawn first, because this one
+    /// doesn't cover the whole screen
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    /// Called when the scene is taken off the stack, or the game closes
+    fn leave(&mut self, _game: &mut Game) {}
+}
+
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+}
+
+impl SceneStack {
+    pub fn new(first: Box<dyn Scene>) -> SceneStack {
+        SceneStack { scenes: vec![first] }
+    }
+
+    pub fn top(&mut self) -> Option<&mut Box<dyn Scene>> {
+        self.scenes.last_mut()
+    }
+
+    /// Carries out a transition. Returns false once there's nothing left
+    /// to run and the game should quit.
+    pub fn apply(&mut self, game: &mut Game, transition: Transition) -> bool {
+        match transition {
+            Transition::None => (),
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop => {
+                if let Some(mut scene) = self.scenes.pop() {
+                    scene.leave(game);
+                }
+            }
+            Transition::Reset(scene) => {
+                self.clear(game);
+                self.scenes.push(scene);
+            }
+            Transition::Quit => self.clear(game),
+        }
+        !self.scenes.is_empty()
+    }
+
+    /// Takes every scene off the stack, top first
+    pub fn clear(&mut self, game: &mut Game) {
+        while let Some(mut scene) = self.scenes.pop() {
+            scene.leave(game);
+        }
+    }
+
+    pub fn draw(&mut self, game: &mut Game, ctx: &mut Context) -> GameResult<()> {
+        let bottom = self.scenes.iter().rposition(|scene| !scene.is_overlay()).unwrap_or(0);
+        for scene in &mut self.scenes[bottom..] {
+            scene.draw(game, ctx)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+//! The game world and the systems that run it, without any windowing,
+//! drawing or other ggez machinery. MainState wraps one of these for the real
+//! game; headless runs drive one directly.
+
+use super::*;
+use replay::{Checksum, Frame, Playback, Recording};
+use save::SaveFile;
+
+pub struct Simulation<'a, 'b> {
+    pub world: World,
+    dispatcher: Dispatcher<'a, 'b>,
+    recording: Option<Recording>,
+    playback: Option<Playback>,
+    /// Set once a playback runs out: whether the world matched the recording
+    replay_result: Option<bool>,
+}
+
+impl<'a, 'b> Simulation<'a, 'b> {
+    pub fn new(level: &Level, arsenal: &Arsenal, physics: &PhysicsConfig) -> Simulation<'a, 'b> {
+        let mut world = Simulation::empty_world(level, arsenal, physics);
+        spawn_level(&mut world, level);
+
+        let mut pools = Pools::new();
+        for _ in 0..100 {
+            pools.player_bullets.add(spawn_player_bullet(&mut world));
+        }
+        for _ in 0..100 {
+            pools.enemy_bullets.add(spawn_enemy_bullet(&mut world));
+        }
+        for _ in 0..512 {
+            pools.particles.add(spawn_particle(&mut world));
+        }
+        world.add_resource(pools);
+
+        Simulation::with_world(world)
+    }
+
+    /// Picks up a saved game. `level` should be the one the save names.
+    pub fn load(save: &SaveFile, level: &Level, arsenal: &Arsenal, physics: &PhysicsConfig) -> Simulation<'a, 'b> {
+        let mut world = Simulation::empty_world(level, arsenal, physics);
+        let mut pools = save.restore(&mut world);
+        for _ in 0..512 {
+            pools.particles.add(spawn_particle(&mut world));
+        }
+        world.add_resource(pools);
+
+        Simulation::with_world(world)
+    }
+
+    /// Captures the world so it can be loaded again later. `level` is the
+    /// path of the level it was started on.
+    pub fn save(&self, level: &str) -> SaveFile {
+        SaveFile::capture(&self.world, level)
+    }
+
+    /// A world with every component registered and every resource added,
+    /// and the level's tilemap, but no entities
+    fn empty_world(level: &Level, arsenal: &Arsenal, physics: &PhysicsConfig) -> World {
+        let mut world = World::new();
+        world.register::<Pos>();
+        world.register::<Vel>();
+        world.register::<IsPlayer>();
+        world.register::<BulletStatus>();
+        world.register::<Facing>();
+        world.register::<Aim>();
+        world.register::<HasGravity>();
+        world.register::<ShootCooldown>();
+        world.register::<IsJumping>();
+        world.register::<Grounded>();
+        world.register::<JumpWindows>();
+        world.register::<TileCollider>();
+        world.register::<IsHook>();
+        world.register::<IsSwingTarget>();
+        world.register::<SwingData_>();
+        world.register::<IsBoss>();
+        world.register::<Health>();
+        world.register::<Damage>();
+        world.register::<HitStun>();
+        world.register::<Invulnerable>();
+        world.register::<Dead>();
+        world.register::<BossPhase>();
+        world.register::<PhaseTimer>();
+        world.register::<IsEnemyBullet>();
+        world.register::<Collider>();
+        world.register::<Patrol>();
+        world.register::<Slamming>();
+        world.register::<Animation>();
+        world.register::<Sprite>();
+        world.register::<Weapons>();
+        world.register::<WeaponPickup>();
+        world.register::<Particle>();
+        world.register::<Emitter>();
+
+        world.add_resource(arsenal.clone());
+        world.add_resource(physics.clone());
+        add_tilemap(&mut world, level);
+
+        world.add_resource(DeltaTime(0.0));
+        world.add_resource(GlobalTime(0.0));
+        world.add_resource(Collisions::default());
+        world.add_resource(Impacts::default());
+        world.add_resource(Effects::default());
+        world.add_resource(Sounds::default());
+        world.add_resource(ParticleRng::default());
+        world.add_resource(InputState::default());
+        world
+    }
+
+    fn with_world(mut world: World) -> Simulation<'a, 'b> {
+        let mut dispatcher = DispatcherBuilder::new()
+            .with(RigidBodyPhysics, "rigid-body-physics", &[])
+            .with(PlayerControl, "player-control", &[])
+            .with(ShootBullets, "shoot-bullets", &["player-control"])
+            .with(DoHook, "do-hook", &["player-control"])
+            .with(SwingPhysics, "swing-physics", &["rigid-body-physics", "player-control", "do-hook"])
+            .with(BossAI, "boss-ai", &["rigid-body-physics", "shoot-bullets"])
+            .with(PatrolAI, "patrol-ai", &["rigid-body-physics"])
+            .with(CollisionSystem, "collision", &["swing-physics", "boss-ai", "patrol-ai"])
+            .with(BulletImpact, "bullet-impact", &["collision"])
+            .with(CollectPickups, "collect-pickups", &["collision"])
+            .with(DamageSystem, "damage", &["collision", "bullet-impact"])
+            .with(CameraFollow, "camera-follow", &["swing-physics", "damage"])
+            .with(ParticleSystem, "particles", &["rigid-body-physics", "player-control", "shoot-bullets", "do-hook", "bullet-impact"])
+            .with(AnimationSystem, "animation", &["swing-physics", "shoot-bullets", "damage"])
+            .build();
+        dispatcher.setup(&mut world.res);
+
+        Simulation {
+            world,
+            dispatcher,
+            recording: None,
+            playback: None,
+            replay_result: None,
+        }
+    }
+
+    /// Advances the world by one tick of `dt` seconds, using whatever inputs
+    /// are currently held.
+    pub fn step(&mut self, dt: f32) {
+        {
+            let mut delta = self.world.write_resource::<DeltaTime>();
+            *delta = DeltaTime(dt);
+        }
+        {
+            // Advance simulation time by whole ticks so swings don't
+            // depend on how fast frames are being drawn.
+            let mut time = self.world.write_resource::<GlobalTime>();
+            time.0 += f64::from(dt);
+        }
+        {
+            let mut input = self.world.write_resource::<InputState>();
+            // A replay overrides whatever is actually being pressed
+            if let Some(frame) = self.playback.as_mut().and_then(|p| p.next_frame()) {
+                frame.apply(&mut input);
+            }
+            if let Some(ref mut recording) = self.recording {
+                recording.push(Frame::capture(&input));
+            }
+            input.update_flags();
+        }
+
+        self.dispatcher.dispatch(&self.world.res);
+        self.world.maintain();
+        self.grow_pools();
+
+        // Presses only count as "just pressed" for a single tick
+        self.world.write_resource::<InputState>().just_pressed.clear();
+
+        if self.playback.as_ref().map_or(false, |p| p.is_finished()) {
+            let playback = self.playback.take().unwrap();
+            let checksum = self.checksum();
+            self.replay_result = Some(playback.expected_checksum().map_or(true, |c| c == checksum));
+            // Don't leave the last recorded inputs held down
+            self.world.write_resource::<InputState>().keys.clear();
+        }
+    }
+
+    /// Tops up any pool that ran dry this tick, so the next request succeeds
+    fn grow_pools(&mut self) {
+        let (player, enemy) = {
+            let mut pools = self.world.write_resource::<Pools>();
+            (pools.player_bullets.take_growth(), pools.enemy_bullets.take_growth())
+        };
+        for _ in 0..player {
+            let ent = spawn_player_bullet(&mut self.world);
+            self.world.write_resource::<Pools>().player_bullets.add(ent);
+        }
+        for _ in 0..enemy {
+            let ent = spawn_enemy_bullet(&mut self.world);
+            self.world.write_resource::<Pools>().enemy_bullets.add(ent);
+        }
+    }
+
+    /// Starts logging the inputs of every tick from now on
+    pub fn start_recording(&mut self, level: &str) {
+        self.recording = Some(Recording::new(level));
+    }
+
+    /// Stops recording and returns everything recorded so far, stamped with
+    /// the current checksum
+    pub fn finish_recording(&mut self) -> Option<Recording> {
+        let checksum = self.checksum();
+        self.recording.take().map(|mut recording| {
+            recording.checksum = Some(checksum);
+            recording
+        })
+    }
+
+    /// Feeds `recording`'s inputs to the following ticks instead of the real
+    /// ones. It should be played back on a freshly loaded copy of the same
+    /// level it was recorded on.
+    pub fn start_playback(&mut self, recording: Recording) {
+        self.playback = Some(Playback::new(recording));
+        self.replay_result = None;
+    }
+
+    /// Once a playback has run out, returns whether the world ended up where
+    /// the recording said it should. Only returns it once.
+    pub fn take_replay_result(&mut self) -> Option<bool> {
+        self.replay_result.take()
+    }
+
+    /// The sounds the world has asked for since the last call
+    pub fn take_sounds(&mut self) -> Vec<Sound> {
+        self.world.write_resource::<Sounds>().0.drain(..).collect()
+    }
+
+    /// A hash of every entity's Pos and Vel, for detecting desyncs
+    pub fn checksum(&self) -> u64 {
+        let entities = self.world.entities();
+        let pos = self.world.read_storage::<Pos>();
+        let vel = self.world.read_storage::<Vel>();
+
+        let mut checksum = Checksum::new();
+        for (ent, pos, vel) in (&*entities, pos.maybe(), vel.maybe()).join() {
+            if pos.is_none() && vel.is_none() {
+                continue;
+            }
+            checksum.add_u32(ent.id());
+            if let Some(pos) = pos {
+                checksum.add_f32(pos.0.x);
+                checksum.add_f32(pos.0.y);
+            }
+            if let Some(vel) = vel {
+                checksum.add_f32(vel.0.x);
+                checksum.add_f32(vel.0.y);
+            }
+        }
+        checksum.finish()
+    }
+
+    pub fn press(&mut self, input: Input) {
+        self.world.write_resource::<InputState>().register_keypress(input);
+    }
+
+    pub fn release(&mut self, input: Input) {
+        self.world.write_resource::<InputState>().unregister_keypress(input);
+    }
+
+    /// Lets go of every button and recenters the stick
+    pub fn release_all(&mut self) {
+        let mut input = self.world.write_resource::<InputState>();
+        input.keys.clear();
+        input.set_stick(Some(0.0), Some(0.0));
+    }
+
+    pub fn set_stick(&mut self, x: Option<f32>, y: Option<f32>) {
+        self.world.write_resource::<InputState>().set_stick(x, y);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub const TICK: f32 = 1.0 / 60.0;
+
+    fn read_resource(name: &str) -> String {
+        let file = path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join(name.trim_start_matches('/'));
+        fs::read_to_string(&file).unwrap()
+    }
+
+    /// The default level, guns and physics, as shipped
+    pub fn arena_data() -> (Level, Arsenal, PhysicsConfig) {
+        (
+            Level::parse(&read_resource(DEFAULT_LEVEL)).unwrap(),
+            Arsenal::parse(&read_resource(WEAPONS)).unwrap(),
+            PhysicsConfig::parse(&read_resource(PHYSICS)).unwrap(),
+        )
+    }
+
+    pub fn arena() -> Simulation<'static, 'static> {
+        let (level, arsenal, physics) = arena_data();
+        Simulation::new(&level, &arsenal, &physics)
+    }
+
+    fn player(sim: &Simulation) -> Entity {
+        let entities = sim.world.entities();
+        let is_player = sim.world.read_storage::<IsPlayer>();
+        (&*entities, &is_player).join().map(|(ent, _)| ent).next().unwrap()
+    }
+
+    fn player_pos(sim: &Simulation) -> Point2 {
+        sim.world.read_storage::<Pos>().get(player(sim)).unwrap().0
+    }
+
+    fn player_vel(sim: &Simulation) -> Vector2 {
+        sim.world.read_storage::<Vel>().get(player(sim)).unwrap().0
+    }
+
+    fn run(sim: &mut Simulation, ticks: u32) {
+        for _ in 0..ticks {
+            sim.step(TICK);
+        }
+    }
+
+    /// The player starts in mid air and falls to the arena floor
+    fn landed() -> Simulation<'static, 'static> {
+        let mut sim = arena();
+        run(&mut sim, 60);
+        sim
+    }
+
+    #[test]
+    fn falls_and_lands() {
+        let sim = landed();
+        // The floor's top is at -160, and the player is 32 tall
+        assert!((player_pos(&sim).y - -144.0).abs() < 0.01);
+        assert_eq!(player_vel(&sim).y, 0.0);
+        assert!(sim.world.read_storage::<Grounded>().get(player(&sim)).unwrap().0);
+    }
+
+    #[test]
+    fn runs_up_to_walk_speed() {
+        let mut sim = landed();
+        let start = player_pos(&sim);
+        sim.press(Input::LEFT);
+        run(&mut sim, 30);
+        assert_eq!(player_vel(&sim).x, -100.0);
+        assert!(player_pos(&sim).x < start.x - 30.0);
+
+        // And slows to a stop after letting go
+        sim.release(Input::LEFT);
+        run(&mut sim, 30);
+        assert_eq!(player_vel(&sim).x, 0.0);
+    }
+
+    #[test]
+    fn jumps_and_cuts_short_jumps() {
+        let mut sim = landed();
+        sim.press(Input::JUMP);
+        sim.step(TICK);
+        assert!(player_vel(&sim).y > 200.0);
+        assert!(sim.world.read_storage::<IsJumping>().get(player(&sim)).unwrap().0);
+
+        sim.release(Input::JUMP);
+        sim.step(TICK);
+        assert!(player_vel(&sim).y <= 120.0);
+    }
+
+    #[test]
+    fn jumps_with_no_grace_periods() {
+        let (level, arsenal, mut physics) = arena_data();
+        physics.coyote_time = 0.0;
+        physics.jump_buffer = 0.0;
+        assert!(physics.validate().is_ok());
+        let mut sim = Simulation::new(&level, &arsenal, &physics);
+        run(&mut sim, 60);
+
+        sim.press(Input::JUMP);
+        sim.step(TICK);
+        assert!(player_vel(&sim).y > 200.0);
+    }
+
+    #[test]
+    fn jump_presses_in_mid_air_expire_without_a_buffer() {
+        let (level, arsenal, mut physics) = arena_data();
+        physics.jump_buffer = 0.0;
+        let mut sim = Simulation::new(&level, &arsenal, &physics);
+        // Still falling from the spawn point
+        run(&mut sim, 5);
+        sim.press(Input::JUMP);
+        sim.step(TICK);
+        sim.release(Input::JUMP);
+        run(&mut sim, 60);
+        assert!(!sim.world.read_storage::<IsJumping>().get(player(&sim)).unwrap().0);
+    }
+
+    #[test]
+    fn shoots() {
+        let mut sim = arena();
+        sim.press(Input::SHOOT);
+        sim.step(TICK);
+
+        let bullet = sim.world.read_storage::<BulletStatus>();
+        let is_enemy_bullet = sim.world.read_storage::<IsEnemyBullet>();
+        let vel = sim.world.read_storage::<Vel>();
+        let flying: Vec<f32> = (&bullet, &vel, !&is_enemy_bullet).join()
+            .filter(|&(bullet, _, _)| match *bullet {
+                BulletStatus::Alive => true,
+                BulletStatus::Dead => false,
+            })
+            .map(|(_, vel, _)| vel.0.norm())
+            .collect();
+        assert_eq!(flying.len(), 1);
+        // The pistol's speed
+        assert!((flying[0] - 600.0).abs() < 0.01);
+        assert_eq!(sim.world.read_resource::<Pools>().player_bullets.stats().in_use, 1);
+    }
+
+    #[test]
+    fn bullets_only_hurt_the_first_thing_they_hit() {
+        let mut sim = arena();
+        let spot = Point2::new(200.0, -144.0);
+        let walkers = [spawn_walker(&mut sim.world, spot), spawn_walker(&mut sim.world, spot)];
+        {
+            let bullet = sim.world.write_resource::<Pools>().player_bullets.acquire().unwrap();
+            *sim.world.write_storage::<BulletStatus>().get_mut(bullet).unwrap() = BulletStatus::Alive;
+            sim.world.write_storage::<Pos>().get_mut(bullet).unwrap().0 = spot;
+        }
+        sim.step(TICK);
+
+        let health = sim.world.read_storage::<Health>();
+        let lost: f32 = walkers.iter().map(|&ent| 30.0 - health.get(ent).unwrap().hp).sum();
+        assert_eq!(lost, 10.0);
+    }
+
+    #[test]
+    fn ignores_a_hook_right_on_top_of_the_player() {
+        // The player spawns on the middle hook
+        let mut sim = arena();
+        sim.press(Input::TOOL);
+        run(&mut sim, 10);
+        assert!(sim.world.read_storage::<SwingData_>().get(player(&sim)).is_none());
+        let pos = player_pos(&sim);
+        assert!(pos.x.is_finite() && pos.y.is_finite());
+    }
+
+    #[test]
+    fn ignores_hooks_below_the_player() {
+        let mut sim = arena();
+        spawn_hook(&mut sim.world, Point2::new(30.0, -60.0));
+        sim.press(Input::TOOL);
+        sim.step(TICK);
+        assert!(sim.world.read_storage::<SwingData_>().get(player(&sim)).is_none());
+    }
+
+    #[test]
+    fn reeling_out_stops_at_the_floor() {
+        let mut sim = landed();
+        sim.press(Input::JUMP);
+        run(&mut sim, 20);
+        sim.press(Input::TOOL);
+        sim.step(TICK);
+        sim.release(Input::TOOL);
+        assert!(sim.world.read_storage::<SwingData_>().get(player(&sim)).is_some());
+
+        // Paying out the whole rope would put the player's feet 6 units
+        // under the floor
+        sim.press(Input::DOWN);
+        run(&mut sim, 120);
+        assert!(player_pos(&sim).y >= -144.01);
+    }
+
+    #[test]
+    fn hooks_and_swings() {
+        // Jump up within reach of the middle hook, at (0, 0)
+        let mut sim = landed();
+        sim.press(Input::JUMP);
+        run(&mut sim, 20);
+        sim.press(Input::TOOL);
+        sim.step(TICK);
+        sim.release(Input::TOOL);
+
+        let dist = {
+            let swing_data = sim.world.read_storage::<SwingData_>();
+            let sd = swing_data.get(player(&sim)).expect("the player should be swinging");
+            assert!(sd.dist >= 24.0 && sd.dist <= 150.0);
+            sd.dist
+        };
+        assert_eq!(sim.world.read_storage::<IsSwingTarget>().join().count(), 1);
+
+        // The rope keeps the player at the same distance from the hook
+        sim.press(Input::RIGHT);
+        run(&mut sim, 30);
+        let pos = player_pos(&sim);
+        assert!((pos.coords.norm() - dist).abs() < 0.01);
+    }
+}
@@ -0,0 +1,253 @@
+//! Level geometry.
+//!
+//! The world is a grid of square tiles, each empty, solid or a one-way
+//! platform. Anything with a TileCollider moves through it by sweeping its
+//! box along one axis at a time, stopping at whatever it runs into.
+
+use ggez::graphics::{Point2, Vector2};
+
+/// Keeps boxes that are exactly flush with a tile edge from counting as
+/// overlapping it.
+const EPSILON: f32 = 0.001;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Tile {
+    Empty,
+    /// Blocks movement from every side. Walls are just columns of these.
+    Solid,
+    /// One-way platform. Only blocks things falling onto it from above.
+    Platform,
+}
+
+/// The level geometry: a grid of square tiles.
+///
+/// Rows are counted upward from the bottom of the map, to match the world
+/// coordinate system. Anything outside the grid is empty space.
+#[derive(Debug)]
+pub struct Tilemap {
+    pub tile_size: f32,
+    /// World position of the bottom-left corner of the map
+    pub origin: Point2,
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+}
+
+impl Tilemap {
+    pub fn new(width: usize, height: usize, tile_size: f32, origin: Point2) -> Self {
+        Tilemap {
+            tile_size,
+            origin,
+            width,
+            height,
+            tiles: vec![Tile::Empty; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, col: i32, row: i32) -> Tile {
+        if col < 0 || row < 0 || col as usize >= self.width || row as usize >= self.height {
+            Tile::Empty
+        } else {
+            self.tiles[row as usize * self.width + col as usize]
+        }
+    }
+
+    pub fn set(&mut self, col: usize, row: usize, tile: Tile) {
+        assert!(col < self.width && row < self.height);
+        self.tiles[row * self.width + col] = tile;
+    }
+
+    pub fn tile_at(&self, point: Point2) -> Tile {
+        self.get(self.col_of(point.x), self.row_of(point.y))
+    }
+
+    /// Whether `point` lies inside the map's rectangle, tiles or not
+    pub fn contains(&self, point: Point2) -> bool {
+        let max_x = self.origin.x + self.width as f32 * self.tile_size;
+        let max_y = self.origin.y + self.height as f32 * self.tile_size;
+        point.x >= self.origin.x && point.x < max_x && point.y >= self.origin.y && point.y < max_y
+    }
+
+    /// The (bottom-left, top-right) corners of the map in world coordinates
+    pub fn bounds(&self) -> (Point2, Point2) {
+        (self.origin, self.tile_corner(self.width as i32, self.height as i32))
+    }
+
+    /// World position of the bottom-left corner of a tile
+    pub fn tile_corner(&self, col: i32, row: i32) -> Point2 {
+        Point2::new(
+            self.origin.x + col as f32 * self.tile_size,
+            self.origin.y + row as f32 * self.tile_size,
+        )
+    }
+
+    fn col_of(&self, x: f32) -> i32 {
+        ((x - self.origin.x) / self.tile_size).floor() as i32
+    }
+
+    fn row_of(&self, y: f32) -> i32 {
+        ((y - self.origin.y) / self.tile_size).floor() as i32
+    }
+
+    /// Moves a box centered at `pos` with half-extents `half` by `dx`
+    /// horizontally, stopping at the first solid tile. Returns the new center
+    /// x, and whether the box was blocked.
+    pub fn sweep_x(&self, pos: Point2, half: Vector2, dx: f32) -> (f32, bool) {
+        let mut x = pos.x;
+        for step in self.steps(dx) {
+            let (next_x, blocked) = self.step_x(Point2::new(x, pos.y), half, step);
+            x = next_x;
+            if blocked {
+                return (x, true);
+            }
+        }
+        (x, false)
+    }
+
+    /// Like sweep_x, but vertical. Platforms stop the box only if it was
+    /// entirely above them before moving.
+    pub fn sweep_y(&self, pos: Point2, half: Vector2, dy: f32) -> (f32, bool) {
+        let mut y = pos.y;
+        for step in self.steps(dy) {
+            let (next_y, blocked) = self.step_y(Point2::new(pos.x, y), half, step);
+            y = next_y;
+            if blocked {
+                return (y, true);
+            }
+        }
+        (y, false)
+    }
+
+    /// Splits a move into pieces no longer than half a tile, so fast things
+    /// can't skip over a tile in a single step.
+    fn steps(&self, d: f32) -> Vec<f32> {
+        if d == 0.0 {
+            return vec![];
+        }
+        let n = (d.abs() / (self.tile_size / 2.0)).ceil().max(1.0) as usize;
+        vec![d / n as f32; n]
+    }
+
+    fn step_x(&self, pos: Point2, half: Vector2, dx: f32) -> (f32, bool) {
+        let x = pos.x + dx;
+        let bottom = self.row_of(pos.y - half.y);
+        let top = self.row_of(pos.y + half.y - EPSILON);
+        let col = if dx > 0.0 {
+            self.col_of(x + half.x)
+        } else {
+            self.col_of(x - half.x)
+        };
+
+        let blocked = (bottom..top + 1).any(|row| self.get(col, row) == Tile::Solid);
+        if !blocked {
+            (x, false)
+        } else if dx > 0.0 {
+            (self.tile_corner(col, 0).x - half.x, true)
+        } else {
+            (self.tile_corner(col + 1, 0).x + half.x, true)
+        }
+    }
+
+    fn step_y(&self, pos: Point2, half: Vector2, dy: f32) -> (f32, bool) {
+        let y = pos.y + dy;
+        let left = self.col_of(pos.x - half.x);
+        let right = self.col_of(pos.x + half.x - EPSILON);
+
+        if dy > 0.0 {
+            let row = self.row_of(y + half.y);
+            let blocked = (left..right + 1).any(|col| self.get(col, row) == Tile::Solid);
+            if blocked {
+                (self.tile_corner(0, row).y - half.y, true)
+            } else {
+                (y, false)
+            }
+        } else {
+            let row = self.row_of(y - half.y);
+            let row_top = self.tile_corner(0, row + 1).y;
+            let was_above = pos.y - half.y >= row_top - EPSILON;
+            let blocked = (left..right + 1).any(|col| match self.get(col, row) {
+                Tile::Solid => true,
+                Tile::Platform => was_above,
+                Tile::Empty => false,
+            });
+            if blocked {
+                (row_top + half.y, true)
+            } else {
+                (y, false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5x4 map of 10 unit tiles: a solid floor, a wall at the right and a
+    /// platform across the middle of the third row
+    fn map() -> Tilemap {
+        let mut tilemap = Tilemap::new(5, 4, 10.0, Point2::origin());
+        for col in 0..5 {
+            tilemap.set(col, 0, Tile::Solid);
+        }
+        for row in 0..4 {
+            tilemap.set(4, row, Tile::Solid);
+        }
+        tilemap.set(1, 2, Tile::Platform);
+        tilemap.set(2, 2, Tile::Platform);
+        tilemap
+    }
+
+    const HALF: f32 = 2.0;
+
+    fn half() -> Vector2 {
+        Vector2::new(HALF, HALF)
+    }
+
+    #[test]
+    fn moves_freely_through_empty_space() {
+        assert_eq!(map().sweep_x(Point2::new(10.0, 15.0), half(), 5.0), (15.0, false));
+    }
+
+    #[test]
+    fn stops_against_walls() {
+        let (x, blocked) = map().sweep_x(Point2::new(10.0, 15.0), half(), 25.0);
+        assert!(blocked);
+        assert_eq!(x, 40.0 - HALF);
+    }
+
+    #[test]
+    fn fast_moves_dont_skip_walls() {
+        let (x, blocked) = map().sweep_x(Point2::new(10.0, 15.0), half(), 1000.0);
+        assert!(blocked);
+        assert_eq!(x, 40.0 - HALF);
+    }
+
+    #[test]
+    fn lands_on_the_floor() {
+        let (y, blocked) = map().sweep_y(Point2::new(5.0, 18.0), half(), -20.0);
+        assert!(blocked);
+        assert_eq!(y, 10.0 + HALF);
+    }
+
+    #[test]
+    fn platforms_only_block_from_above() {
+        let tilemap = map();
+        // Falling onto it
+        let (y, blocked) = tilemap.sweep_y(Point2::new(15.0, 35.0), half(), -10.0);
+        assert!(blocked);
+        assert_eq!(y, 30.0 + HALF);
+        // Jumping up through it
+        assert_eq!(tilemap.sweep_y(Point2::new(15.0, 15.0), half(), 10.0), (25.0, false));
+        // Falling out of it after jumping through
+        assert_eq!(tilemap.sweep_y(Point2::new(15.0, 25.0), half(), -5.0), (20.0, false));
+    }
+}
@@ -0,0 +1,217 @@
+//! Guns.
+//!
+//! Every gun in the game is described in a RON file, so they can be tuned
+//! without touching code:
+//!
+//! ```text
+//! Arsenal([
+//!     (
+//!         name: "pistol",
+//!         fire_interval: 0.1,
+//!         spread: 0.0,
+//!         projectiles: 1,
+//!         speed: 600.0,
+//!         damage: 10.0,
+//!         radius: 5.0,
+//!         sprite: "bullet",
+//!         magazine: 12,
+//!         reload_time: 0.8,
+//!     ),
+//! ])
+//! ```
+//!
+//! The player starts out holding the first gun listed, and picks the others
+//! up in levels.
+
+use ron;
+
+use assets::Assets;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WeaponDef {
+    pub name: String,
+    /// Seconds between shots while the trigger is held
+    pub fire_interval: f32,
+    /// Total angle, in radians, that a shot's projectiles fan out over
+    #[serde(default)]
+    pub spread: f32,
+    /// Projectiles per shot
+    #[serde(default = "one")]
+    pub projectiles: u32,
+    pub speed: f32,
+    pub damage: f32,
+    /// Collision radius of each projectile
+    pub radius: f32,
+    /// Image name from the asset manifest
+    pub sprite: String,
+    #[serde(default = "one_f32")]
+    pub sprite_scale: f32,
+    /// Shots before having to reload
+    pub magazine: u32,
+    pub reload_time: f32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn one_f32() -> f32 {
+    1.0
+}
+
+impl WeaponDef {
+    /// Directions of the projectiles in one shot, as angles from straight
+    /// ahead, spread evenly across `spread`
+    pub fn angles(&self) -> Vec<f32> {
+        if self.projectiles <= 1 {
+            return vec![0.0];
+        }
+        let step = self.spread / (self.projectiles - 1) as f32;
+        (0..self.projectiles)
+            .map(|i| -self.spread / 2.0 + step * i as f32)
+            .collect()
+    }
+}
+
+/// Every gun there is, as loaded from the weapons file
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Arsenal(Vec<WeaponDef>);
+
+impl Arsenal {
+    pub fn parse(source: &str) -> Result<Arsenal, String> {
+        let arsenal: Arsenal = ron::de::from_str(source).map_err(|e| {
+            format!("line {}, column {}: {}", e.position.line, e.position.col, e.code)
+        })?;
+
+        if arsenal.0.is_empty() {
+            return Err("there are no weapons".to_owned());
+        }
+        for (i, def) in arsenal.0.iter().enumerate() {
+            if arsenal.0[..i].iter().any(|d| d.name == def.name) {
+                return Err(format!("there are two weapons named {:?}", def.name));
+            }
+            if def.projectiles == 0 || def.magazine == 0 {
+                return Err(format!("{} needs at least one projectile and one shot per magazine", def.name));
+            }
+            if def.fire_interval <= 0.0 {
+                return Err(format!("{} needs a positive fire_interval", def.name));
+            }
+        }
+        Ok(arsenal)
+    }
+
+    /// Makes sure every gun's sprite is in the asset manifest, so a typo
+    /// shows up when the game starts rather than when the gun is first shot
+    pub fn check_sprites(&self, assets: &Assets) -> Result<(), String> {
+        for def in &self.0 {
+            if assets.image(&def.sprite).is_err() {
+                return Err(format!("{} uses sprite {:?}, which isn't in the asset manifest", def.name, def.sprite));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WeaponDef> {
+        self.0.iter().find(|def| def.name == name)
+    }
+
+    /// What the player starts out with
+    pub fn starting_weapon(&self) -> &WeaponDef {
+        &self.0[0]
+    }
+}
+
+/// A gun someone is carrying
+#[derive(Clone, Debug)]
+pub struct Weapon {
+    pub def: WeaponDef,
+    /// Shots left in the magazine
+    pub ammo: u32,
+    /// Seconds until the reload finishes, or 0 if not reloading
+    pub reload: f32,
+}
+
+impl Weapon {
+    pub fn new(def: &WeaponDef) -> Weapon {
+        Weapon {
+            def: def.clone(),
+            ammo: def.magazine,
+            reload: 0.0,
+        }
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload > 0.0
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        if self.reload > 0.0 {
+            self.reload -= dt;
+            if self.reload <= 0.0 {
+                self.reload = 0.0;
+                self.ammo = self.def.magazine;
+            }
+        }
+    }
+
+    /// Uses up a shot, starting a reload if that was the last one. Returns
+    /// false if the gun can't fire right now.
+    pub fn take_shot(&mut self) -> bool {
+        if self.is_reloading() || self.ammo == 0 {
+            return false;
+        }
+        self.ammo -= 1;
+        if self.ammo == 0 {
+            self.reload = self.def.reload_time;
+        }
+        true
+    }
+}
+
+/// The guns an entity is carrying, and which one is out
+#[derive(Component, Debug)]
+pub struct Weapons {
+    pub carried: Vec<Weapon>,
+    pub current: usize,
+}
+
+impl Weapons {
+    pub fn new(def: &WeaponDef) -> Weapons {
+        Weapons {
+            carried: vec![Weapon::new(def)],
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> &Weapon {
+        &self.carried[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Weapon {
+        &mut self.carried[self.current]
+    }
+
+    /// Puts away the current gun and takes out the next one
+    pub fn cycle(&mut self) {
+        self.current = (self.current + 1) % self.carried.len();
+    }
+
+    /// Takes out the gun described by `def`, adding it if it's new or
+    /// refilling it if we already had one
+    pub fn give(&mut self, def: &WeaponDef) {
+        match self.carried.iter().position(|w| w.def.name == def.name) {
+            Some(i) => {
+                self.carried[i] = Weapon::new(def);
+                self.current = i;
+            }
+            None => {
+                self.carried.push(Weapon::new(def));
+                self.current = self.carried.len() - 1;
+            }
+        }
+    }
+}
+
+/// A gun lying around waiting to be picked up
+#[derive(Component, Debug)]
+pub struct WeaponPickup(pub String);